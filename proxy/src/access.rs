@@ -0,0 +1,261 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+use crate::page::escape;
+
+/// How long a parent lets a blocked host through for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Approval {
+    Hour,
+    Day,
+    Always,
+}
+
+impl Approval {
+    pub fn parse(word: &str) -> Option<Approval> {
+        match word {
+            "hour" => Some(Approval::Hour),
+            "day" => Some(Approval::Day),
+            "always" => Some(Approval::Always),
+            _ => None,
+        }
+    }
+}
+
+/// A kid asking for a blocked host from its block page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessRequest {
+    pub id: u64,
+    /// The device's name or address, as in `Client::name`.
+    pub device: String,
+    pub host: String,
+    /// What they said it's for.
+    pub note: String,
+    pub at: DateTime<Utc>,
+}
+
+/// A host a parent has let one device through to, along with everything under it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Grant {
+    pub device: String,
+    pub host: String,
+    /// When it runs out; never for permanent grants.
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Grant {
+    fn covers(&self, device: &str, host: &str, now: DateTime<Utc>) -> bool {
+        self.device == device
+            && (host == self.host || host.strip_suffix(self.host.as_str()).is_some_and(|sub| sub.ends_with('.')))
+            && self.until.is_none_or(|until| now < until)
+    }
+}
+
+/** Requests for access waiting on a parent, and the grants they've made.
+
+Grants are checked ahead of the rules, quotas included, so an approval takes effect on the
+next request. They're kept in a file if there is one, a `DEVICE HOST UNTIL` line each with
+`UNTIL` an RFC 3339 time or `always`. Pending requests only last until a restart.
+*/
+#[derive(Debug, Default)]
+pub struct Access {
+    pub path: Option<PathBuf>,
+    pub pending: Vec<AccessRequest>,
+    pub grants: Vec<Grant>,
+    next_id: u64,
+}
+
+impl Access {
+    /// Reads grants from `path`, or starts with none if it doesn't exist yet.
+    pub fn load(path: PathBuf) -> Result<Access, String> {
+        let mut access = Access { path: Some(path.clone()), ..Access::default() };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(access),
+            Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+        };
+        for line in text.lines() {
+            let bad = || format!("{}: can't read {:?}", path.display(), line);
+            let [device, host, until] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(bad());
+            };
+            let until = match until {
+                "always" => None,
+                time => Some(time.parse().map_err(|_| bad())?),
+            };
+            access.grants.push(Grant { device: device.to_string(), host: host.to_string(), until });
+        }
+        Ok(access)
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let text: String = self
+            .grants
+            .iter()
+            .map(|g| format!("{} {} {}\n", g.device, g.host, g.until.map_or(String::from("always"), |u| u.to_rfc3339())))
+            .collect();
+        let tmp = path.with_extension("tmp");
+        if let Err(e) = std::fs::write(&tmp, text).and_then(|_| std::fs::rename(&tmp, path)) {
+            println!("couldn't save grants to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Queues a request, unless the device is already waiting on that host.
+    pub fn ask(&mut self, device: &str, host: &str, note: &str, now: DateTime<Utc>) {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if self.pending.iter().any(|r| r.device == device && r.host == host) {
+            return;
+        }
+        self.next_id += 1;
+        self.pending.push(AccessRequest { id: self.next_id, device: device.to_string(), host, note: note.trim().to_string(), at: now });
+    }
+
+    /// When `device` asked for `host`, if it's still waiting.
+    pub fn asked(&self, device: &str, host: &str) -> Option<DateTime<Utc>> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.pending.iter().find(|r| r.device == device && r.host == host).map(|r| r.at)
+    }
+
+    /// Grants a pending request, replacing any grant it already had.
+    pub fn approve(&mut self, id: u64, approval: Approval, now: DateTime<Utc>) -> Option<&Grant> {
+        let i = self.pending.iter().position(|r| r.id == id)?;
+        let request = self.pending.remove(i);
+        let until = match approval {
+            Approval::Hour => Some(now + Duration::hours(1)),
+            Approval::Day => Some(now + Duration::days(1)),
+            Approval::Always => None,
+        };
+        self.grants.retain(|g| !(g.device == request.device && g.host == request.host));
+        self.grants.push(Grant { device: request.device, host: request.host, until });
+        self.save();
+        self.grants.last()
+    }
+
+    pub fn deny(&mut self, id: u64) {
+        self.pending.retain(|r| r.id != id);
+    }
+
+    pub fn revoke(&mut self, device: &str, host: &str) {
+        self.grants.retain(|g| !(g.device == device && g.host == host));
+        self.save();
+    }
+
+    /// The grant letting `device` through to `host` at `now`, dropping any that have run out.
+    pub fn granted(&mut self, device: &str, host: &str, now: DateTime<Utc>) -> Option<&Grant> {
+        let before = self.grants.len();
+        self.grants.retain(|g| g.until.is_none_or(|until| now < until));
+        if self.grants.len() != before {
+            self.save();
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.grants.iter().find(|g| g.covers(device, &host, now))
+    }
+
+    /// The admin page listing pending requests and current grants, with times shown by `local`.
+    pub fn admin_page(&self, local: impl Fn(DateTime<Utc>) -> NaiveDateTime) -> String {
+        let time = |t: DateTime<Utc>| local(t).format("%a %H:%M").to_string();
+        let pending: String = self
+            .pending
+            .iter()
+            .map(|r| {
+                let buttons: String = [("hour", "1 hour"), ("day", "1 day"), ("always", "Always")]
+                    .iter()
+                    .map(|(value, label)| format!("<button name=\"for\" value=\"{}\">{}</button>", value, label))
+                    .collect();
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><form method=\"post\" action=\"/approve\"><input type=\"hidden\" name=\"id\" value=\"{}\">{}</form><form method=\"post\" action=\"/deny\"><input type=\"hidden\" name=\"id\" value=\"{}\"><button>Deny</button></form></td></tr>\n",
+                    time(r.at),
+                    escape(&r.device),
+                    escape(&r.host),
+                    escape(&r.note),
+                    r.id,
+                    buttons,
+                    r.id
+                )
+            })
+            .collect();
+        let grants: String = self
+            .grants
+            .iter()
+            .map(|g| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td><form method=\"post\" action=\"/revoke\"><input type=\"hidden\" name=\"device\" value=\"{}\"><input type=\"hidden\" name=\"host\" value=\"{}\"><button>Revoke</button></form></td></tr>\n",
+                    escape(&g.device),
+                    escape(&g.host),
+                    g.until.map_or(String::from("always"), |u| format!("until {}", time(u))),
+                    escape(&g.device),
+                    escape(&g.host)
+                )
+            })
+            .collect();
+        let none = |rows: String, colspan: usize| if rows.is_empty() { format!("<tr><td colspan=\"{}\">None</td></tr>", colspan) } else { rows };
+        include_str!("../admin.html").replace("{{pending}}", &none(pending, 5)).replace("{{grants}}", &none(grants, 4))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_approve() {
+        let now = utc("2024-06-03T16:00:00Z");
+        let mut access = Access::default();
+        access.ask("tablet", "Www.Khanacademy.org", "math homework", now);
+        access.ask("tablet", "www.khanacademy.org", "again", now);
+        access.ask("tablet", "games.example.com", "", now);
+        assert_eq!(access.pending.len(), 2);
+        assert_eq!(access.asked("tablet", "www.khanacademy.org"), Some(now));
+        assert_eq!(access.asked("tablet", "WWW.KhanAcademy.org."), Some(now));
+        assert!(access.granted("tablet", "www.khanacademy.org", now).is_none());
+        let id = access.pending[0].id;
+        assert_eq!(access.approve(id, Approval::Hour, now).unwrap().until, Some(utc("2024-06-03T17:00:00Z")));
+        assert_eq!(access.pending.len(), 1);
+        assert!(access.granted("tablet", "cdn.www.khanacademy.org", utc("2024-06-03T16:59:00Z")).is_some());
+        assert!(access.granted("phone", "www.khanacademy.org", now).is_none());
+        assert!(access.granted("tablet", "www.khanacademy.org", utc("2024-06-03T17:00:00Z")).is_none());
+        // Run-out grants are dropped.
+        assert!(access.grants.is_empty());
+        let id = access.pending[0].id;
+        access.deny(id);
+        assert!(access.pending.is_empty());
+        assert!(access.approve(id, Approval::Always, now).is_none());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("proxy-grants-{}.txt", std::process::id()));
+        let now = utc("2024-06-03T16:00:00Z");
+        let mut access = Access { path: Some(path.clone()), ..Access::default() };
+        access.ask("tablet", "www.khanacademy.org", "", now);
+        access.ask("laptop", "scratch.mit.edu", "", now);
+        access.approve(1, Approval::Day, now);
+        access.approve(2, Approval::Always, now);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "tablet www.khanacademy.org 2024-06-04T16:00:00+00:00\nlaptop scratch.mit.edu always\n");
+        let mut loaded = Access::load(path.clone()).unwrap();
+        assert_eq!(loaded.grants, access.grants);
+        loaded.revoke("laptop", "scratch.mit.edu");
+        assert_eq!(Access::load(path.clone()).unwrap().grants.len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_admin_page() {
+        let now = utc("2024-06-03T16:00:00Z");
+        let mut access = Access::default();
+        let page = access.admin_page(|t| t.naive_utc());
+        assert_eq!(page.matches("<td colspan").count(), 2);
+        access.ask("tablet", "www.example.com", "<script>", now);
+        let page = access.admin_page(|t| t.naive_utc());
+        assert!(page.contains("<td>Mon 16:00</td><td>tablet</td><td>www.example.com</td><td>&lt;script&gt;</td>"));
+        assert!(page.contains("<button name=\"for\" value=\"always\">Always</button>"));
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Where Linux lists the MAC addresses it has seen on the local network.
+const ARP_TABLE: &str = "/proc/net/arp";
+
+/** A named device, known by its addresses or its network card.
+
+MAC addresses survive DHCP handing out a new IP, but the proxy only sees IPs, so they're
+looked up in the ARP table of the machine it runs on. That only works for devices on the
+same network segment.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    pub name: String,
+    pub ips: Vec<IpAddr>,
+    /// Lowercase, colon-separated.
+    pub macs: Vec<String>,
+}
+
+/// Who's asking: the address a request came from, and the device it belongs to if known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Client {
+    pub ip: IpAddr,
+    pub device: Option<String>,
+}
+
+impl Client {
+    pub fn new(ip: IpAddr) -> Client {
+        Client { ip: ip.to_canonical(), device: None }
+    }
+
+    /// The device's name, or the address for unknown devices; what usage is counted against.
+    pub fn name(&self) -> String {
+        self.device.clone().unwrap_or_else(|| self.ip.to_string())
+    }
+}
+
+/// Normalizes `aa:bb:cc:dd:ee:ff` or `AA-BB-CC-DD-EE-FF`, or returns `None` if it isn't a MAC address.
+pub fn parse_mac(word: &str) -> Option<String> {
+    let parts: Vec<&str> = word.split([':', '-']).collect();
+    if parts.len() != 6 || parts.iter().any(|p| p.len() != 2 || u8::from_str_radix(p, 16).is_err()) {
+        return None;
+    }
+    Some(parts.join(":").to_ascii_lowercase())
+}
+
+/// The IP to MAC mapping in the text of `/proc/net/arp`, leaving out incomplete entries.
+pub fn parse_arp(table: &str) -> HashMap<IpAddr, String> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ip = fields.first()?.parse().ok()?;
+            let mac = parse_mac(fields.get(3)?)?;
+            (mac != "00:00:00:00:00:00").then_some((ip, mac))
+        })
+        .collect()
+}
+
+/// The MAC address this machine has seen for `ip`, if any.
+pub fn mac_of(ip: IpAddr) -> Option<String> {
+    let table = std::fs::read_to_string(ARP_TABLE).ok()?;
+    parse_arp(&table).remove(&ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_arp() {
+        let table = "IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.20     0x1         0x2         AA:BB:CC:DD:EE:01     *        eth0
+192.168.1.21     0x1         0x0         00:00:00:00:00:00     *        eth0
+";
+        let arp = parse_arp(table);
+        assert_eq!(arp.len(), 1);
+        assert_eq!(arp[&"192.168.1.20".parse::<IpAddr>().unwrap()], "aa:bb:cc:dd:ee:01");
+        assert_eq!(parse_mac("AA-BB-CC-DD-EE-0F").as_deref(), Some("aa:bb:cc:dd:ee:0f"));
+        assert_eq!(parse_mac("::1"), None);
+    }
+}
@@ -0,0 +1,389 @@
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::log::{Kind, Source};
+use crate::proxy::Proxy;
+use crate::tls::Cursor;
+
+/// Record types, and the internet class.
+const A: u16 = 1;
+const CNAME: u16 = 5;
+const AAAA: u16 = 28;
+const IN: u16 = 1;
+/// Response codes.
+const FORMERR: u8 = 1;
+const SERVFAIL: u8 = 2;
+/// How long our own answers may be cached, short so a change isn't held up for long.
+const ANSWER_TTL: u32 = 60;
+/// How long to wait for the upstream resolver.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest message we'll take; bigger ones need TCP, which isn't served.
+const MAX_MESSAGE: usize = 4096;
+
+/// What a DNS query asks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Question {
+    /// Lowercase, without the trailing dot.
+    pub name: String,
+    pub kind: u16,
+    /// Where the question ends in the query, so answers can follow it.
+    end: usize,
+}
+
+impl Question {
+    /// The question in `query`, if it's a standard query with exactly one.
+    pub fn parse(query: &[u8]) -> Option<Question> {
+        let mut message = Cursor(query);
+        let header = message.take(12)?;
+        // Not a response, opcode 0, one question.
+        if header[2] & 0xf8 != 0 || header[4..6] != [0, 1] {
+            return None;
+        }
+        let mut labels = vec![];
+        loop {
+            let length = message.u8()? as usize;
+            if length == 0 {
+                break;
+            }
+            // Longer means a compression pointer, which a question has no use for.
+            if length > 63 {
+                return None;
+            }
+            let label = message.take(length)?;
+            // Host names only, so a dot or a NUL inside a label can't pass for another name.
+            if !label.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'-' || *b == b'_') {
+                return None;
+            }
+            labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        }
+        let kind = message.u16()?;
+        message.u16()?;
+        Some(Question { name: labels.join("."), kind, end: query.len() - message.0.len() })
+    }
+}
+
+/** A DNS server for devices that ignore proxy settings, using the proxy's rules.
+
+Blocked names are answered with `block_ip`, where the proxy should be listening on port
+80 so the device gets the block page; HTTPS to them finds nothing listening there and
+fails. Search engines and video sites are answered with their safe-search names, for
+the clients `Rules::safe_search` says, and everything else is passed to the `upstream`
+resolver as is. Queries come from the device's own address, so groups and devices work
+as they do for the proxy, and so do grants. A lookup of a name under a quota counts the
+minute it's made in.
+
+Only UDP is served. Queries it can't read are refused rather than passed on, so an odd
+one can't get a blocked name through.
+*/
+pub struct Dns {
+    pub proxy: Arc<Proxy>,
+    pub upstream: SocketAddr,
+    pub block_ip: IpAddr,
+}
+
+impl Dns {
+    /// Answers queries until the socket fails, each on its own thread.
+    pub fn serve(self: Arc<Self>, socket: UdpSocket) {
+        let socket = Arc::new(socket);
+        let mut buf = [0; MAX_MESSAGE];
+        loop {
+            let (n, from) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => {
+                    println!("dns receive failed: {}", e);
+                    continue;
+                }
+            };
+            let (dns, socket, query) = (self.clone(), socket.clone(), buf[..n].to_vec());
+            thread::spawn(move || {
+                if let Some(response) = dns.answer(&query, from.ip()) {
+                    if let Err(e) = socket.send_to(&response, from) {
+                        println!("{}: {}", from, e);
+                    }
+                }
+            });
+        }
+    }
+
+    /// The response to `query` from `ip`, or `None` if it's too short to answer at all.
+    pub fn answer(&self, query: &[u8], ip: IpAddr) -> Option<Vec<u8>> {
+        if query.len() < 12 {
+            return None;
+        }
+        let Some(question) = Question::parse(query) else {
+            return Some(response(query, None, FORMERR, &[]));
+        };
+        let client = self.proxy.client(ip);
+        if self.proxy.check(&client, &question.name, Source::Dns).is_err() {
+            let answers = match (question.kind, self.block_ip) {
+                (A, IpAddr::V4(ip)) => vec![(A, ANSWER_TTL, ip.octets().to_vec())],
+                (AAAA, IpAddr::V6(ip)) => vec![(AAAA, ANSWER_TTL, ip.octets().to_vec())],
+                // The name exists, just not with this kind of record.
+                _ => vec![],
+            };
+            return Some(response(query, Some(&question), 0, &answers));
+        }
+        if let Some(target) = self.proxy.rules.safe_search(&client, &question.name) {
+            self.proxy.log(Source::Dns, &client, Kind::SafeSearch, &question.name, target.to_string());
+            return Some(self.rewrite(query, &question, target));
+        }
+        match self.forward(query) {
+            Ok(response) => Some(response),
+            Err(e) => {
+                println!("couldn't ask {} about {}: {}", self.upstream, question.name, e);
+                Some(response(query, Some(&question), SERVFAIL, &[]))
+            }
+        }
+    }
+
+    /** Answers with `target` as the name's CNAME, along with the target's addresses from upstream.
+
+    Any chain of names upstream has for the target is left out, with its addresses put
+    straight under the target, so the answer reads name, target, addresses. Only
+    addresses are passed on; other kinds of record get just the CNAME.
+    */
+    fn rewrite(&self, query: &[u8], question: &Question, target: &str) -> Vec<u8> {
+        let name = encode_name(target);
+        let mut target_query = query[..12].to_vec();
+        target_query[4..12].copy_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+        target_query.extend_from_slice(&name);
+        target_query.extend(question.kind.to_be_bytes());
+        target_query.extend(IN.to_be_bytes());
+        let addresses = match question.kind {
+            A | AAAA => match self.forward(&target_query).map(|r| records(&r, question.kind)) {
+                Ok(Some(addresses)) => addresses,
+                Ok(None) => vec![],
+                Err(e) => {
+                    println!("couldn't ask {} about {}: {}", self.upstream, target, e);
+                    return response(query, Some(question), SERVFAIL, &[]);
+                }
+            },
+            _ => vec![],
+        };
+        let mut out = response(query, Some(question), 0, &[(CNAME, ANSWER_TTL, name.clone())]);
+        // The addresses are named with a pointer to the target in the CNAME.
+        let target_at = (out.len() - name.len()) as u16;
+        for (ttl, data) in &addresses {
+            out.extend((0xc000 | target_at).to_be_bytes());
+            out.extend(question.kind.to_be_bytes());
+            out.extend(IN.to_be_bytes());
+            out.extend(ttl.to_be_bytes());
+            out.extend((data.len() as u16).to_be_bytes());
+            out.extend(data);
+        }
+        out[6..8].copy_from_slice(&(1 + addresses.len() as u16).to_be_bytes());
+        out
+    }
+
+    /// Asks the upstream resolver, from a port of its own so answers can't get mixed up.
+    fn forward(&self, query: &[u8]) -> std::io::Result<Vec<u8>> {
+        let any: SocketAddr = if self.upstream.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let socket = UdpSocket::bind(any)?;
+        socket.set_read_timeout(Some(UPSTREAM_TIMEOUT))?;
+        socket.connect(self.upstream)?;
+        socket.send(query)?;
+        let mut buf = [0; MAX_MESSAGE];
+        loop {
+            let n = socket.recv(&mut buf)?;
+            if n >= 2 && buf[..2] == query[..2] {
+                return Ok(buf[..n].to_vec());
+            }
+        }
+    }
+}
+
+/// `www.example.com` as DNS labels.
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut out = vec![];
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        out.push(label.len().min(63) as u8);
+        out.extend(&label.as_bytes()[..label.len().min(63)]);
+    }
+    out.push(0);
+    out
+}
+
+/// The TTL and data of every `kind` record in a response's answers.
+fn records(response: &[u8], kind: u16) -> Option<Vec<(u32, Vec<u8>)>> {
+    let mut message = Cursor(response);
+    let header = message.take(12)?;
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    let answers = u16::from_be_bytes([header[6], header[7]]);
+    for _ in 0..questions {
+        skip_name(&mut message)?;
+        message.take(4)?;
+    }
+    let mut found = vec![];
+    for _ in 0..answers {
+        skip_name(&mut message)?;
+        let record_kind = message.u16()?;
+        message.u16()?;
+        let ttl = message.take(4)?;
+        let length = message.u16()? as usize;
+        let data = message.take(length)?;
+        if record_kind == kind {
+            found.push((u32::from_be_bytes([ttl[0], ttl[1], ttl[2], ttl[3]]), data.to_vec()));
+        }
+    }
+    Some(found)
+}
+
+/// Moves past a name, which may end in a pointer to another.
+fn skip_name(message: &mut Cursor) -> Option<()> {
+    loop {
+        match message.u8()? {
+            0 => return Some(()),
+            length if length >= 0xc0 => return message.u8().map(|_| ()),
+            length if length > 63 => return None,
+            length => message.take(length as usize)?,
+        };
+    }
+}
+
+/// A response to `query` with `answers`, as (type, TTL, data) records for the name asked about.
+fn response(query: &[u8], question: Option<&Question>, code: u8, answers: &[(u16, u32, Vec<u8>)]) -> Vec<u8> {
+    let mut out = query[..2].to_vec();
+    // A response, keeping the opcode and whether recursion was asked for, which it was available for.
+    out.push(0x80 | (query[2] & 0x79));
+    out.push(0x80 | code);
+    let counts = [question.is_some() as u16, answers.len() as u16, 0, 0];
+    out.extend(counts.iter().flat_map(|c| c.to_be_bytes()));
+    if let Some(question) = question {
+        out.extend_from_slice(&query[12..question.end]);
+        for (kind, ttl, data) in answers {
+            // The name is a pointer to the question's.
+            out.extend([0xc0, 12]);
+            out.extend(kind.to_be_bytes());
+            out.extend(IN.to_be_bytes());
+            out.extend(ttl.to_be_bytes());
+            out.extend((data.len() as u16).to_be_bytes());
+            out.extend(data);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use crate::schedule::SystemClock;
+
+    fn query(id: u16, name: &str, kind: u16) -> Vec<u8> {
+        let mut out = id.to_be_bytes().to_vec();
+        out.extend([1, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        out.extend(encode_name(name));
+        out.extend(kind.to_be_bytes());
+        out.extend(IN.to_be_bytes());
+        out
+    }
+
+    /// A stand-in upstream resolver that says safe-search names are at 216.239.38.120 and every other name at 93.184.216.34.
+    fn resolver() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            loop {
+                let (n, from) = socket.recv_from(&mut buf).unwrap();
+                let question = Question::parse(&buf[..n]).unwrap();
+                let address = if question.name.contains("safe") { vec![216, 239, 38, 120] } else { vec![93, 184, 216, 34] };
+                socket.send_to(&response(&buf[..n], Some(&question), 0, &[(A, 300, address)]), from).unwrap();
+            }
+        });
+        addr
+    }
+
+    fn lookup(port: u16, query: &[u8]) -> Vec<u8> {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        socket.send_to(query, ("127.0.0.1", port)).unwrap();
+        let mut buf = [0; 512];
+        let n = socket.recv(&mut buf).unwrap();
+        buf[..n].to_vec()
+    }
+
+    fn start(upstream: SocketAddr, rules: &str) -> u16 {
+        let proxy = Proxy::new(Rules::parse(rules).unwrap(), Box::new(SystemClock));
+        let dns = Dns { proxy: Arc::new(proxy), upstream, block_ip: "10.0.0.1".parse().unwrap() };
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        thread::spawn(move || Arc::new(dns).serve(socket));
+        port
+    }
+
+    /// The response code, how many answers there are, and the last answer's TTL and data.
+    fn summary(response: &[u8]) -> (u8, u16, &[u8]) {
+        (response[3] & 0xf, u16::from_be_bytes([response[6], response[7]]), &response[response.len() - 10..])
+    }
+
+    #[test]
+    fn test_question() {
+        let asked = query(7, "WWW.Example.com", AAAA);
+        assert_eq!(Question::parse(&asked), Some(Question { name: String::from("www.example.com"), kind: AAAA, end: asked.len() }));
+        for end in 0..asked.len() {
+            assert_eq!(Question::parse(&asked[..end]), None);
+        }
+        // Responses aren't questions.
+        let mut answered = asked.clone();
+        answered[2] |= 0x80;
+        assert_eq!(Question::parse(&answered), None);
+        assert_eq!(Question::parse(&query(8, "_dmarc.my-site.example.com", A)).unwrap().name, "_dmarc.my-site.example.com");
+        for odd in ["www.exa mple.com", "www.example.com\0.org", "www.exämple.com"] {
+            assert_eq!(Question::parse(&query(9, odd, A)), None);
+        }
+        let mut dotted = query(10, "www.exampleXcom", A);
+        let x = dotted.iter().position(|b| *b == b'X').unwrap();
+        dotted[x] = b'.';
+        assert_eq!(Question::parse(&dotted), None);
+    }
+
+    #[test]
+    fn test_sinkhole() {
+        let port = start(resolver(), "deny *.example.com");
+        let blocked = lookup(port, &query(1, "www.Example.com", A));
+        assert_eq!(blocked[..4], [0, 1, 0x81, 0x80]);
+        assert_eq!(summary(&blocked), (0, 1, &[0, 0, 0, 60, 0, 4, 10, 0, 0, 1][..]));
+        // No IPv6 address to send them to.
+        assert_eq!(summary(&lookup(port, &query(2, "www.example.com", AAAA))).1, 0);
+        let allowed = lookup(port, &query(3, "www.example.org", A));
+        assert_eq!(allowed[..2], [0, 3]);
+        assert_eq!(summary(&allowed), (0, 1, &[0, 0, 1, 44, 0, 4, 93, 184, 216, 34][..]));
+        let mut odd = query(4, "www.example.com", A);
+        odd[5] = 2;
+        assert_eq!(summary(&lookup(port, &odd)).0, FORMERR);
+        assert_eq!(summary(&lookup(port, &query(5, "www.example.com\0.org", A))).0, FORMERR);
+    }
+
+    #[test]
+    fn test_safe_search() {
+        let port = start(resolver(), "group kids 127.0.0.1");
+        let response = lookup(port, &query(6, "www.google.com", A));
+        let question_end = 12 + encode_name("www.google.com").len() + 4;
+        let target = encode_name("forcesafesearch.google.com");
+        let mut cname = vec![0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, target.len() as u8];
+        cname.extend(&target);
+        assert_eq!(u16::from_be_bytes([response[6], response[7]]), 2);
+        assert_eq!(response[question_end..question_end + cname.len()], cname);
+        // The address is named by a pointer to the CNAME's target.
+        let pointer = 0xc000 | (question_end + 12) as u16;
+        assert_eq!(response[question_end + cname.len()..], [&pointer.to_be_bytes()[..], &[0, 1, 0, 1, 0, 0, 1, 44, 0, 4, 216, 239, 38, 120]].concat());
+        assert_eq!(records(&response, A), Some(vec![(300, vec![216, 239, 38, 120])]));
+        // Other kinds of record get just the CNAME.
+        assert_eq!(summary(&lookup(port, &query(7, "www.google.com", 16))).1, 1);
+        // Only for groups, unless the rules say otherwise.
+        let port = start(resolver(), "");
+        assert_eq!(summary(&lookup(port, &query(8, "www.google.com", A))), (0, 1, &[0, 0, 1, 44, 0, 4, 93, 184, 216, 34][..]));
+    }
+
+    #[test]
+    fn test_upstream_down() {
+        // Nothing answers on a port that was just freed.
+        let free = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let port = start(free, "deny *.example.com");
+        let response = lookup(port, &query(5, "www.example.org", A));
+        assert_eq!(summary(&response).0, SERVFAIL);
+    }
+}
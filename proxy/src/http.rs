@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+/// Longest request head we'll read before giving up on a client.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// Longest form body we'll read.
+const MAX_FORM: u64 = 16 * 1024;
+
+/// Headers that describe one hop of the connection and mustn't be passed on.
+const HOP_BY_HOP: [&str; 6] = ["connection", "proxy-connection", "keep-alive", "proxy-authorization", "te", "upgrade"];
+
+/** The request line and headers of an HTTP/1.x request.
+
+A forward proxy sees targets in absolute form (`GET http://example.com/ HTTP/1.1`),
+`CONNECT host:port` for tunnels, and origin form (`GET / HTTP/1.1`) from anything that
+talks to it directly.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /** Reads a request head, up to and including the blank line.
+
+    Returns `Ok(None)` if the client closed the connection before sending anything.
+    */
+    pub fn read(reader: &mut impl BufRead) -> io::Result<Option<Request>> {
+        let mut line = String::new();
+        let mut head_len = 0;
+        let mut read_line = |line: &mut String| -> io::Result<usize> {
+            line.clear();
+            let n = reader.read_line(line)?;
+            head_len += n;
+            if head_len > MAX_HEAD {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "request head too long"));
+            }
+            Ok(n)
+        };
+        if read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad request line {:?}", line.trim_end())));
+        };
+        let mut request = Request { method: method.to_string(), target: target.to_string(), version: version.to_string(), headers: vec![] };
+        loop {
+            if read_line(&mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed in request head"));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                return Ok(Some(request));
+            }
+            match header.split_once(':') {
+                Some((name, value)) => request.headers.push((name.trim().to_string(), value.trim().to_string())),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad header {:?}", header))),
+            }
+        }
+    }
+
+    /// The first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// Length of the request body, if it says.
+    pub fn content_length(&self) -> Option<u64> {
+        self.header("content-length")?.parse().ok()
+    }
+
+    /** Where an absolute-form request is going, as (host, port, path).
+
+    Only `http://` targets can be forwarded; anything else gives `None`.
+    */
+    pub fn destination(&self) -> Option<(String, u16, String)> {
+        let rest = self.target.strip_prefix("http://")?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = split_host_port(authority, 80)?;
+        Some((host, port, path.to_string()))
+    }
+
+    /// Where an origin-form request sent straight to us was meant for, going by its `Host` header.
+    pub fn host_destination(&self) -> Option<(String, u16, String)> {
+        if !self.target.starts_with('/') {
+            return None;
+        }
+        let (host, port) = split_host_port(self.header("host")?, 80)?;
+        Some((host, port, self.target.clone()))
+    }
+
+    /** The head to send to `host:port`: origin-form target, no hop-by-hop headers, and one request per connection.
+
+    `Host` names the destination whatever the client sent, as RFC 9112 asks of a proxy, so a
+    request checked against one host can't reach another that shares its server.
+    */
+    pub fn upstream_head(&self, host: &str, port: u16, path: &str) -> String {
+        let mut authority = if host.contains(':') { format!("[{}]", host) } else { host.to_string() };
+        if port != 80 {
+            authority.push_str(&format!(":{}", port));
+        }
+        let mut head = format!("{} {} {}\r\nHost: {}\r\n", self.method, path, self.version, authority);
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("host") && !HOP_BY_HOP.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        head
+    }
+}
+
+/** Splits `host[:port]` and lowercases the host, using `default_port` if there's no port.
+
+IPv6 literals keep their brackets off: `[::1]:8080` is `("::1", 8080)`.
+*/
+pub fn split_host_port(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (host, after) = rest.split_once(']')?;
+        (host, after.strip_prefix(':'))
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => default_port,
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_ascii_lowercase(), port))
+}
+
+/// Reads and decodes an `application/x-www-form-urlencoded` request body.
+pub fn read_form(reader: &mut impl Read, request: &Request) -> io::Result<HashMap<String, String>> {
+    let mut body = String::new();
+    reader.take(request.content_length().unwrap_or(0).min(MAX_FORM)).read_to_string(&mut body)?;
+    Ok(parse_form(&body))
+}
+
+/// `a=1&b=x+y` as a map, with `+` and `%XX` decoded.
+pub fn parse_form(body: &str) -> HashMap<String, String> {
+    body.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let mut bytes = vec![];
+    let mut input = text.bytes();
+    while let Some(b) = input.next() {
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = input.clone().take(2).collect();
+                match std::str::from_utf8(&hex).ok().and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(decoded) if hex.len() == 2 => {
+                        bytes.push(decoded);
+                        input.nth(1);
+                    }
+                    _ => bytes.push(b'%'),
+                }
+            }
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// A `303 See Other` to `location`, for after a form is posted.
+pub fn redirect(stream: &mut impl Write, location: &str) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 303 See Other\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", location)?;
+    stream.flush()
+}
+
+/// Writes a complete response that closes the connection.
+pub fn respond(stream: &mut impl Write, status: u16, reason: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, reason, content_type, body.len())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_request() {
+        let mut input = "GET http://Example.com:8080/a?b=c HTTP/1.1\r\nHost: example.com\r\nProxy-Connection: keep-alive\r\nContent-Length: 3\r\n\r\nabc".as_bytes();
+        let request = Request::read(&mut input).unwrap().unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.header("HOST"), Some("example.com"));
+        assert_eq!(request.content_length(), Some(3));
+        assert_eq!(request.destination(), Some((String::from("example.com"), 8080, String::from("/a?b=c"))));
+        assert_eq!(request.upstream_head("example.com", 8080, "/a?b=c"), "GET /a?b=c HTTP/1.1\r\nHost: example.com:8080\r\nContent-Length: 3\r\nConnection: close\r\n\r\n");
+        assert!(request.upstream_head("::1", 80, "/").starts_with("GET / HTTP/1.1\r\nHost: [::1]\r\nContent-Length"));
+        assert_eq!(request.host_destination(), None);
+        let direct = Request::read(&mut "GET /a HTTP/1.1\r\nHost: Example.com\r\n\r\n".as_bytes()).unwrap().unwrap();
+        assert_eq!((direct.destination(), direct.host_destination()), (None, Some((String::from("example.com"), 80, String::from("/a")))));
+        // The body is left for the caller.
+        assert_eq!(input, b"abc");
+        assert_eq!(Request::read(&mut "".as_bytes()).unwrap(), None);
+        assert!(Request::read(&mut "nonsense\r\n\r\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_form() {
+        let form = parse_form("host=www.example.com&note=for+school%21+50%25&bad=%zz&empty");
+        assert_eq!(form["host"], "www.example.com");
+        assert_eq!(form["note"], "for school! 50%");
+        assert_eq!(form["bad"], "%zz");
+        assert_eq!(form["empty"], "");
+        assert_eq!(parse_form("").len(), 0);
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(split_host_port("example.com", 80), Some((String::from("example.com"), 80)));
+        assert_eq!(split_host_port("example.com:443", 80), Some((String::from("example.com"), 443)));
+        assert_eq!(split_host_port("[::1]:8080", 80), Some((String::from("::1"), 8080)));
+        assert_eq!(split_host_port("example.com:http", 80), None);
+        assert_eq!(split_host_port(":80", 80), None);
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::NaiveDateTime;
+
+/// How big the log gets before it's rotated.
+const MAX_BYTES: u64 = 10 * 1024 * 1024;
+/// How many rotated logs are kept.
+const KEEP: usize = 5;
+/// How times are written: local, to the second.
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// What made a decision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Proxy,
+    Dns,
+}
+
+/// What happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Allow,
+    Block,
+    /// Answered with the host's safe-search name, which is the detail.
+    SafeSearch,
+    /// Time counted against a quota, as the category and minutes since midnight, like `video 960-965`.
+    Used,
+    /// Asked a parent for access from the block page, with the note as the detail.
+    Ask,
+    /// Given access by a parent, for as long as the detail says.
+    Grant,
+}
+
+impl Source {
+    fn parse(word: &str) -> Option<Source> {
+        match word {
+            "proxy" => Some(Source::Proxy),
+            "dns" => Some(Source::Dns),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Proxy => "proxy",
+            Source::Dns => "dns",
+        })
+    }
+}
+
+impl Kind {
+    fn parse(word: &str) -> Option<Kind> {
+        match word {
+            "allow" => Some(Kind::Allow),
+            "block" => Some(Kind::Block),
+            "safesearch" => Some(Kind::SafeSearch),
+            "used" => Some(Kind::Used),
+            "ask" => Some(Kind::Ask),
+            "grant" => Some(Kind::Grant),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Allow => "allow",
+            Kind::Block => "block",
+            Kind::SafeSearch => "safesearch",
+            Kind::Used => "used",
+            Kind::Ask => "ask",
+            Kind::Grant => "grant",
+        })
+    }
+}
+
+/// One decision about one device and host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// In the rules' timezone.
+    pub time: NaiveDateTime,
+    pub source: Source,
+    /// As in `Client::name`.
+    pub device: String,
+    pub kind: Kind,
+    pub host: String,
+    /// The rule that decided, or what `kind` says.
+    pub detail: String,
+}
+
+impl Event {
+    pub fn parse(line: &str) -> Option<Event> {
+        let [time, source, device, kind, host, detail] = line.splitn(6, '\t').collect::<Vec<_>>()[..] else {
+            return None;
+        };
+        Some(Event {
+            time: NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok()?,
+            source: Source::parse(source)?,
+            device: device.to_string(),
+            kind: Kind::parse(kind)?,
+            host: host.to_string(),
+            detail: detail.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Hosts come from requests, so any field could try to start a line or a column of its own.
+        let clean = |field: &str| field.replace(['\t', '\r', '\n'], " ");
+        write!(f, "{}\t{}\t{}\t{}\t{}\t{}", self.time.format(TIME_FORMAT), self.source, clean(&self.device), self.kind, clean(&self.host), clean(&self.detail))
+    }
+}
+
+/** A log of what the proxy and the DNS server decided, for `report`.
+
+Each line is an `Event`: the time, `proxy` or `dns`, the device, `allow`, `block`,
+`safesearch`, `used`, `ask` or `grant`, the host and the detail, separated by tabs. Once the file grows
+past `max_bytes` it's renamed to `FILE.1`, the one before that to `FILE.2` and so on,
+keeping `keep` old files. Without a path nothing is logged.
+*/
+#[derive(Debug, Default)]
+pub struct Log {
+    pub path: Option<PathBuf>,
+    pub max_bytes: u64,
+    pub keep: usize,
+    file: Mutex<Option<File>>,
+}
+
+impl Log {
+    pub fn new(path: PathBuf) -> Log {
+        Log { path: Some(path), max_bytes: MAX_BYTES, keep: KEEP, file: Mutex::default() }
+    }
+
+    pub fn write(&self, event: &Event) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = self.append(path, &mut file, event) {
+            println!("couldn't log to {}: {}", path.display(), e);
+            *file = None;
+        }
+    }
+
+    fn append(&self, path: &Path, file: &mut Option<File>, event: &Event) -> io::Result<()> {
+        if file.is_none() {
+            *file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        let open = file.as_mut().unwrap();
+        writeln!(open, "{}", event)?;
+        if open.metadata()?.len() >= self.max_bytes {
+            *file = None;
+            for n in (1..self.keep).rev() {
+                let older = numbered(path, n);
+                if older.exists() {
+                    fs::rename(older, numbered(path, n + 1))?;
+                }
+            }
+            match self.keep {
+                0 => fs::remove_file(path)?,
+                _ => fs::rename(path, numbered(path, 1))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every event logged at `path`, oldest file first; lines it can't read are skipped.
+pub fn read(path: &Path) -> Result<Vec<Event>, String> {
+    let mut files = vec![path.to_path_buf()];
+    while files.last().unwrap().exists() {
+        files.push(numbered(path, files.len()));
+    }
+    files.pop();
+    if files.is_empty() {
+        return Err(format!("no log at {}", path.display()));
+    }
+    let mut events = vec![];
+    for file in files.iter().rev() {
+        let text = fs::read_to_string(file).map_err(|e| format!("couldn't read {}: {}", file.display(), e))?;
+        events.extend(text.lines().filter_map(Event::parse));
+    }
+    Ok(events)
+}
+
+/// `activity.log.2` for `activity.log`.
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(minute: u32, host: &str) -> Event {
+        Event {
+            time: NaiveDateTime::parse_from_str(&format!("2024-06-03T16:{:02}:00", minute), TIME_FORMAT).unwrap(),
+            source: Source::Proxy,
+            device: String::from("tablet"),
+            kind: Kind::Block,
+            host: host.to_string(),
+            detail: String::from("line 3: deny\tgames"),
+        }
+    }
+
+    #[test]
+    fn test_event() {
+        let line = event(5, "www.roblox.com").to_string();
+        assert_eq!(line, "2024-06-03T16:05:00\tproxy\ttablet\tblock\twww.roblox.com\tline 3: deny games");
+        assert_eq!(Event::parse(&line), Some(Event { detail: String::from("line 3: deny games"), ..event(5, "www.roblox.com") }));
+        assert_eq!(Event::parse("2024-06-03T16:05:00\tproxy\ttablet\tmaybe\tx\t"), None);
+        let sneaky = Event { device: String::from("tab\tlet"), ..event(6, "www.roblox.com\n2024-06-03T16:06:00\tproxy\ttablet\tallow\tx") };
+        let line = sneaky.to_string();
+        assert_eq!(line.lines().count(), 1);
+        let parsed = Event::parse(&line).unwrap();
+        assert_eq!((parsed.device.as_str(), parsed.kind), ("tab let", Kind::Block));
+        assert_eq!(parsed.host, "www.roblox.com 2024-06-03T16:06:00 proxy tablet allow x");
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("proxy-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("activity.log");
+        let line_length = event(0, "a.example.com").to_string().len() as u64 + 1;
+        // Three lines to a file, and two old files kept.
+        let log = Log { max_bytes: 3 * line_length, keep: 2, ..Log::new(path.clone()) };
+        for minute in 0..10 {
+            log.write(&event(minute, &format!("{}.example.com", (b'a' + minute as u8) as char)));
+        }
+        assert!(!numbered(&path, 3).exists());
+        let hosts: Vec<_> = read(&path).unwrap().into_iter().map(|e| e.host).collect();
+        assert_eq!(hosts, ["d.example.com", "e.example.com", "f.example.com", "g.example.com", "h.example.com", "i.example.com", "j.example.com"]);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(read(&path).is_err());
+    }
+}
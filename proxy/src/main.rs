@@ -0,0 +1,196 @@
+use std::net::{IpAddr, SocketAddr, TcpListener, UdpSocket};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+mod access;
+mod devices;
+mod dns;
+mod http;
+mod log;
+mod page;
+mod proxy;
+mod quota;
+mod report;
+mod rules;
+mod schedule;
+mod tls;
+
+use access::Access;
+use dns::Dns;
+use log::Log;
+use proxy::Proxy;
+use quota::Usage;
+use rules::Rules;
+use schedule::{Clock, FixedClock, SystemClock};
+
+/// Where the proxy listens unless told otherwise; 3128 is the usual proxy port.
+const DEFAULT_LISTEN: &str = "127.0.0.1:3128";
+/// Where the admin page is served unless told otherwise.
+const DEFAULT_ADMIN: &str = "127.0.0.1:3129";
+/// Where DNS mode sends the queries it doesn't block unless told otherwise.
+const DEFAULT_UPSTREAM_DNS: &str = "1.1.1.1:53";
+/// Where quota usage is kept between restarts unless told otherwise.
+const DEFAULT_USAGE: &str = "usage.txt";
+/// Where access a parent has granted is kept unless told otherwise.
+const DEFAULT_GRANTS: &str = "grants.txt";
+/// Where decisions are logged unless told otherwise.
+const DEFAULT_LOG: &str = "activity.log";
+
+const USAGE: &str = "usage: proxy [--listen ADDR]... [--admin ADDR] [--dns ADDR [--upstream-dns ADDR] [--block-ip IP]] [--rules FILE] [--usage FILE] [--grants FILE] [--log FILE] [--now 2024-06-03T20:30:00Z]
+       proxy report [--log FILE] [--day 2024-06-03] [--csv FILE] [--html FILE]";
+
+/** A filtering forward proxy for the kids' devices.
+
+Point a device's HTTP and HTTPS proxy settings at the listen address. What's blocked
+comes from the rule file (see `rules::Rules`, and `rules.txt` for an example); without
+one everything is allowed. Blocked pages get a block page from one of the themes in
+`themes/`, and HTTPS to a blocked host is refused, so the browser shows a connection
+error. Everything else goes through untouched.
+
+A blocked page has a button to ask for access. Requests wait on the admin page, at
+`http://127.0.0.1:3129/` or the address given with `--admin`, where a parent can let the
+device through for an hour, a day or for good. It's only open to this machine and to
+clients on the rule file's `admin` line.
+
+For devices that ignore proxy settings, `--dns ADDR` also runs a DNS server on the
+same rules (see `dns::Dns`). Point their DNS at it and blocked names are answered with
+`--block-ip`, by default the DNS address's own; listen on port 80 there as well, with a
+second `--listen`, so they get the block page. Groups get search engines and video
+sites in their safe modes. Other queries go to `--upstream-dns`, 1.1.1.1 unless told
+otherwise.
+
+Time used against quotas is kept in `usage.txt`, or the file given with `--usage`, and
+access a parent has granted in `grants.txt`, or the file given with `--grants`.
+Everything the proxy and DNS server decide goes in `activity.log`, or the file given
+with `--log`, rotated as it grows (see `log::Log`). `--now` pins the clock, for trying
+out a rule file's schedules.
+
+`proxy report` sums up a day of the log for each device: the hosts it asked for most,
+what was blocked and why, and time used against quotas. It's written as CSV to
+`--csv`, as a page to `--html`, or as CSV to the terminal without either.
+*/
+fn main() {
+    let mut listen = vec![];
+    let mut admin = String::from(DEFAULT_ADMIN);
+    let mut rules = Rules::allow_all();
+    let mut clock: Box<dyn Clock> = Box::new(SystemClock);
+    let mut usage_path = PathBuf::from(DEFAULT_USAGE);
+    let mut grants_path = PathBuf::from(DEFAULT_GRANTS);
+    let mut dns = None;
+    let mut upstream_dns: SocketAddr = DEFAULT_UPSTREAM_DNS.parse().unwrap();
+    let mut block_ip = None;
+    let mut log_path = PathBuf::from(DEFAULT_LOG);
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if_eq("report").is_some() {
+        return run_report(args);
+    }
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--listen", Some(addr)) => listen.push(addr),
+            ("--admin", Some(addr)) => admin = addr,
+            ("--rules", Some(path)) => match Rules::load(&path) {
+                Ok(loaded) => rules = loaded,
+                Err(e) => return println!("{}", e),
+            },
+            ("--dns", Some(addr)) => dns = Some(addr),
+            // A resolver's port can be left off.
+            ("--upstream-dns", Some(addr)) => match addr.parse().or_else(|_| addr.parse().map(|ip| SocketAddr::new(ip, 53))) {
+                Ok(addr) => upstream_dns = addr,
+                Err(_) => return println!("bad address {}", addr),
+            },
+            ("--block-ip", Some(ip)) => match ip.parse::<IpAddr>() {
+                Ok(ip) => block_ip = Some(ip),
+                Err(_) => return println!("bad address {}", ip),
+            },
+            ("--usage", Some(path)) => usage_path = PathBuf::from(path),
+            ("--grants", Some(path)) => grants_path = PathBuf::from(path),
+            ("--log", Some(path)) => log_path = PathBuf::from(path),
+            ("--now", Some(time)) => match time.parse() {
+                Ok(time) => clock = Box::new(FixedClock::new(time)),
+                Err(e) => return println!("bad time {}: {}", time, e),
+            },
+            _ => return println!("{}", USAGE),
+        }
+    }
+    let usage = match Usage::load(usage_path) {
+        Ok(usage) => usage,
+        Err(e) => return println!("{}", e),
+    };
+    let access = match Access::load(grants_path) {
+        Ok(access) => access,
+        Err(e) => return println!("{}", e),
+    };
+    if listen.is_empty() {
+        listen.push(String::from(DEFAULT_LISTEN));
+    }
+    let mut listeners = vec![];
+    for addr in &listen {
+        match TcpListener::bind(addr) {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => return println!("couldn't listen on {}: {}", addr, e),
+        }
+    }
+    let admin_listener = match TcpListener::bind(&admin) {
+        Ok(listener) => listener,
+        Err(e) => return println!("couldn't listen on {}: {}", admin, e),
+    };
+    let dns_socket = match dns.as_ref().map(UdpSocket::bind).transpose() {
+        Ok(socket) => socket,
+        Err(e) => return println!("couldn't listen on {}: {}", dns.unwrap_or_default(), e),
+    };
+    println!("listening on {}, admin page on http://{}/", listen.join(", "), admin);
+    let log = Log::new(log_path);
+    let proxy = Arc::new(Proxy { usage: Mutex::new(usage), access: Mutex::new(access), log, ..Proxy::new(rules, clock) });
+    if let Some(socket) = dns_socket {
+        let Some(block_ip) = block_ip.or_else(|| socket.local_addr().ok().map(|a| a.ip()).filter(|ip| !ip.is_unspecified())) else {
+            return println!("DNS on every address needs --block-ip for the block page");
+        };
+        println!("DNS on {}, blocked names go to {}", dns.unwrap_or_default(), block_ip);
+        let dns = Arc::new(Dns { proxy: proxy.clone(), upstream: upstream_dns, block_ip });
+        std::thread::spawn(move || dns.serve(socket));
+    }
+    let admin_proxy = proxy.clone();
+    std::thread::spawn(move || admin_proxy.serve_admin(admin_listener));
+    let quota_proxy = proxy.clone();
+    std::thread::spawn(move || quota_proxy.watch_quotas());
+    let last = listeners.pop().unwrap();
+    for listener in listeners {
+        let proxy = proxy.clone();
+        std::thread::spawn(move || proxy.serve(listener));
+    }
+    proxy.serve(last);
+}
+
+/// `proxy report`: one day of the activity log, summed up per device.
+fn run_report(mut args: impl Iterator<Item = String>) {
+    let mut log_path = PathBuf::from(DEFAULT_LOG);
+    let mut day = chrono::Local::now().date_naive();
+    let (mut csv_path, mut html_path) = (None, None);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--log", Some(path)) => log_path = PathBuf::from(path),
+            ("--day", Some(date)) => match date.parse() {
+                Ok(date) => day = date,
+                Err(e) => return println!("bad day {}: {}", date, e),
+            },
+            ("--csv", Some(path)) => csv_path = Some(path),
+            ("--html", Some(path)) => html_path = Some(path),
+            _ => return println!("{}", USAGE),
+        }
+    }
+    let events = match log::read(&log_path) {
+        Ok(events) => events,
+        Err(e) => return println!("{}", e),
+    };
+    let days = report::summarize(&events, day);
+    if csv_path.is_none() && html_path.is_none() {
+        return print!("{}", report::csv(&days));
+    }
+    for (path, text) in [(csv_path, report::csv(&days)), (html_path, report::html(day, &days))] {
+        if let Some(path) = path {
+            if let Err(e) = std::fs::write(&path, text) {
+                return println!("couldn't write {}: {}", path, e);
+            }
+        }
+    }
+}
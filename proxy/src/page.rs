@@ -0,0 +1,163 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use chrono::{NaiveDateTime, Timelike};
+
+use crate::rules::duration;
+
+/** Where theme files are served from, on any blocked host and on the proxy itself.
+
+The block page stands in for the blocked site, so its images have to come from the same
+host; a path no real site is likely to use keeps them apart from the site's own.
+*/
+pub const ASSET_PREFIX: &str = "/.proxy/themes";
+
+/// Where the block page's "ask for access" form posts to, again on the blocked host.
+pub const REQUEST_PATH: &str = "/.proxy/request";
+
+/// The theme used unless the rules pick another.
+pub const DEFAULT_THEME: &str = "classic";
+
+/// A file a theme's page refers to, served separately so browsers can cache it.
+pub struct Asset {
+    pub name: &'static str,
+    pub content_type: &'static str,
+    pub bytes: &'static [u8],
+}
+
+/** A block page template and the files it uses.
+
+Templates fill in `{{host}}`, `{{reason}}`, `{{until}}` and `{{quota}}`, any of which
+but the host may be empty, `{{request}}`, the form for asking a parent for access, and
+`{{assets}}`, the path the theme's assets are served at.
+*/
+pub struct Theme {
+    pub name: &'static str,
+    pub page: &'static str,
+    pub assets: &'static [Asset],
+}
+
+pub const THEMES: [Theme; 3] = [
+    Theme {
+        name: "classic",
+        page: include_str!("../themes/classic/page.html"),
+        assets: &[Asset { name: "background.webp", content_type: "image/webp", bytes: include_bytes!("../themes/classic/background.webp") }],
+    },
+    Theme { name: "plain", page: include_str!("../themes/plain/page.html"), assets: &[] },
+    Theme { name: "night", page: include_str!("../themes/night/page.html"), assets: &[] },
+];
+
+pub fn theme(name: &str) -> Option<&'static Theme> {
+    THEMES.iter().find(|t| t.name == name)
+}
+
+/// The asset at `path`, like `/.proxy/themes/classic/background.webp`.
+pub fn asset(path: &str) -> Option<&'static Asset> {
+    let (theme_name, file) = path.strip_prefix(ASSET_PREFIX)?.strip_prefix('/')?.split_once('/')?;
+    theme(theme_name)?.assets.iter().find(|a| a.name == file)
+}
+
+/// A tag that changes whenever the asset does, for `If-None-Match`.
+pub fn etag(asset: &Asset) -> String {
+    let mut hasher = DefaultHasher::new();
+    asset.bytes.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// What the block page says.
+pub struct Details {
+    pub host: String,
+    pub reason: String,
+    pub until: String,
+    pub quota: String,
+    /// When they asked a parent for access, if they're still waiting.
+    pub asked: Option<NaiveDateTime>,
+}
+
+impl Theme {
+    pub fn render(&self, details: &Details) -> String {
+        self.page
+            .replace("{{assets}}", &format!("{}/{}", ASSET_PREFIX, self.name))
+            .replace("{{host}}", &escape(&details.host))
+            .replace("{{reason}}", &escape(&details.reason))
+            .replace("{{until}}", &escape(&details.until))
+            .replace("{{quota}}", &escape(&details.quota))
+            .replace("{{request}}", &request_form(details))
+    }
+}
+
+/// The "ask for access" form, or that they're waiting on an answer.
+fn request_form(details: &Details) -> String {
+    match details.asked {
+        Some(at) => format!("<p class=\"request\">You asked for access at {:02}:{:02}. Waiting for a parent.</p>", at.hour(), at.minute()),
+        None => format!(
+            "<form class=\"request\" method=\"post\" action=\"{}\"><input type=\"text\" name=\"note\" placeholder=\"What do you need it for?\"><button>Ask for access</button></form>",
+            REQUEST_PATH
+        ),
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// When a block ends, relative to `now`; both are local times.
+pub fn until(now: NaiveDateTime, open: Option<NaiveDateTime>) -> String {
+    let Some(open) = open else {
+        return String::from("It stays blocked until the rules change.");
+    };
+    let time = format!("{:02}:{:02}", open.hour(), open.minute());
+    match (open.date() - now.date()).num_days() {
+        0 => format!("Open again at {}.", time),
+        1 => format!("Open again tomorrow at {}.", time),
+        _ => format!("Open again on {} at {}.", open.format("%A"), time),
+    }
+}
+
+/// How much of a daily quota is left.
+pub fn quota_left(category: &str, limit: u32, used: u32) -> String {
+    match limit.saturating_sub(used) {
+        0 => format!("No {} time left today ({} a day).", category, duration(limit)),
+        left => format!("{} of {} time left today.", duration(left), category),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let mut details = Details { host: String::from("<b>.example.com"), reason: String::from("line 3: deny *"), until: String::new(), quota: String::new(), asked: None };
+        for theme in &THEMES {
+            let page = theme.render(&details);
+            assert!(page.contains("&lt;b&gt;.example.com"));
+            assert!(!page.contains("{{"), "{} has a field left", theme.name);
+            for file in theme.assets {
+                let path = format!("{}/{}/{}", ASSET_PREFIX, theme.name, file.name);
+                assert!(page.contains(&path));
+                assert_eq!(asset(&path).unwrap().bytes.len(), file.bytes.len());
+            }
+        }
+        assert!(THEMES[0].render(&details).contains("action=\"/.proxy/request\""));
+        details.asked = Some(at(3, 16, 5));
+        assert!(THEMES[0].render(&details).contains("You asked for access at 16:05."));
+        assert!(asset("/.proxy/themes/classic/missing.png").is_none());
+        assert!(asset("/.proxy/themes/../page.html").is_none());
+    }
+
+    #[test]
+    fn test_until() {
+        assert_eq!(until(at(3, 16, 0), Some(at(3, 18, 0))), "Open again at 18:00.");
+        assert_eq!(until(at(3, 21, 0), Some(at(4, 7, 0))), "Open again tomorrow at 07:00.");
+        assert_eq!(until(at(3, 21, 0), Some(at(8, 0, 0))), "Open again on Saturday at 00:00.");
+        assert_eq!(until(at(3, 21, 0), None), "It stays blocked until the rules change.");
+        assert_eq!(quota_left("video", 60, 15), "45m of video time left today.");
+        assert_eq!(quota_left("video", 90, 95), "No video time left today (1h30m a day).");
+    }
+}
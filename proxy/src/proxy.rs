@@ -0,0 +1,752 @@
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{IpAddr, Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::access::{Access, Approval};
+use crate::devices::{self, Client};
+use crate::http::{self, Request};
+use crate::log::{Event, Kind, Log, Source};
+use crate::page::{self, Asset, Details};
+use crate::quota::Usage;
+use crate::rules::{Action, Decision, Quota, Rules};
+use crate::schedule::Clock;
+use crate::tls;
+
+/// How long to wait for a destination to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a new tunnel gets to start its ClientHello, or to finish one it's started.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often open connections are checked against their quotas.
+const QUOTA_CHECK: Duration = Duration::from_secs(15);
+
+/** A forward proxy that passes allowed requests on and answers blocked ones with a block page.
+
+Each client connection gets its own thread and carries one request; the response
+closes it, so there's no keep-alive bookkeeping. HTTPS goes through `CONNECT` tunnels,
+which are checked against both the tunnel's host and the name in the TLS ClientHello but
+never decrypted.
+
+Time spent on hosts under a quota is counted from when a request is let through until
+its connection closes. A quota that runs out stops new requests, and `cut_off` closes any
+connections still open against it, so a video stream can't outlast the quota.
+
+The block page comes from the client's theme in `page::THEMES` and says which rule is in
+the way, when it lifts and how much of any quota is left. Its images are served on the
+blocked host under `page::ASSET_PREFIX`, and to direct requests. Its "ask for access"
+form posts back to the blocked host at `page::REQUEST_PATH`, queueing a request for a
+parent to answer on the admin page, which is served on a listener of its own.
+
+Requests sent straight to the proxy, as from a device whose DNS answers blocked names
+with the proxy's address (see `dns::Dns`), go by their `Host` header: blocked hosts get
+the block page, and anything else is passed on as if it had been asked for through the
+proxy.
+*/
+pub struct Proxy {
+    pub rules: Rules,
+    pub clock: Box<dyn Clock>,
+    pub usage: Mutex<Usage>,
+    pub access: Mutex<Access>,
+    pub log: Log,
+    /// The ports this proxy listens on, which it won't forward to itself.
+    pub ports: Mutex<Vec<u16>>,
+    /// How long a new tunnel gets to send its ClientHello.
+    pub hello_timeout: Duration,
+    /// Visits under a quota that are still open, by usage id.
+    pub visits: Mutex<HashMap<u64, OpenVisit>>,
+}
+
+/// A visit under a quota while it's open, with the connections to close if the quota runs out.
+pub struct OpenVisit {
+    client: Client,
+    host: String,
+    quota: Quota,
+    source: Source,
+    streams: Vec<TcpStream>,
+}
+
+/// An allowed visit to a host, counted against any quota until it's dropped.
+pub struct Visit<'a> {
+    proxy: &'a Proxy,
+    /// The usage id, if it's under a quota.
+    id: Option<u64>,
+}
+
+impl Visit<'_> {
+    /// Has `cut_off` close `stream` if the visit's quota runs out while it's open.
+    fn watch(&self, stream: &TcpStream) -> io::Result<()> {
+        let Some(id) = self.id else {
+            return Ok(());
+        };
+        if let Some(open) = self.proxy.visits.lock().unwrap().get_mut(&id) {
+            open.streams.push(stream.try_clone()?);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Visit<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.id else {
+            return;
+        };
+        let Some(open) = self.proxy.visits.lock().unwrap().remove(&id) else {
+            return;
+        };
+        let now = self.proxy.rules.local(self.proxy.clock.now());
+        let Some(minutes) = self.proxy.usage.lock().unwrap().close(id, now) else {
+            return;
+        };
+        let detail = format!("{} {}-{}", open.quota.category, minutes.start(), minutes.end());
+        self.proxy.log(open.source, &open.client, Kind::Used, &open.host, detail);
+    }
+}
+
+impl Proxy {
+    /// A proxy that keeps quota usage and grants in memory only, and logs nothing.
+    pub fn new(rules: Rules, clock: Box<dyn Clock>) -> Proxy {
+        Proxy {
+            rules,
+            clock,
+            usage: Mutex::new(Usage::default()),
+            access: Mutex::new(Access::default()),
+            log: Log::default(),
+            ports: Mutex::new(vec![]),
+            hello_timeout: HELLO_TIMEOUT,
+            visits: Mutex::default(),
+        }
+    }
+
+    /// Notes a decision in the activity log, as of now.
+    pub fn log(&self, source: Source, client: &Client, kind: Kind, host: &str, detail: String) {
+        let time = self.rules.local(self.clock.now());
+        self.log.write(&Event { time, source, device: client.name(), kind, host: host.to_string(), detail });
+    }
+
+    /// Who `ip` is, looking up its MAC address if the rules name devices by one.
+    pub fn client(&self, ip: IpAddr) -> Client {
+        let mac = if self.rules.uses_macs() { devices::mac_of(ip) } else { None };
+        self.rules.client(ip, mac.as_deref())
+    }
+
+    /// Checks `host` for `client`, including any quota and grant, and logs what it decides.
+    pub fn check(&self, client: &Client, host: &str, source: Source) -> Result<Visit<'_>, Decision> {
+        let now = self.clock.now();
+        let mut visit = Visit { proxy: self, id: None };
+        if self.access.lock().unwrap().granted(&client.name(), host, now).is_some() {
+            self.log(source, client, Kind::Allow, host, String::from("granted by a parent"));
+            return Ok(visit);
+        }
+        let mut decision = self.rules.check(client, host, now);
+        if let (true, Some(quota)) = (decision.allowed(), self.rules.quota(client, host)) {
+            let local = self.rules.local(now);
+            let mut usage = self.usage.lock().unwrap();
+            if usage.used(&client.name(), &quota.category, local) >= quota.minutes {
+                decision = Decision { action: Action::Deny, reason: format!("{} used up", quota) };
+            } else {
+                let id = usage.open(&client.name(), &quota.category, local);
+                // Taken while holding `usage`, in the same order as `cut_off`.
+                let open = OpenVisit { client: client.clone(), host: host.to_string(), quota: quota.clone(), source, streams: vec![] };
+                self.visits.lock().unwrap().insert(id, open);
+                visit.id = Some(id);
+            }
+        }
+        if !decision.allowed() {
+            self.log(source, client, Kind::Block, host, decision.reason.clone());
+            return Err(decision);
+        }
+        self.log(source, client, Kind::Allow, host, decision.reason);
+        Ok(visit)
+    }
+
+    /** Closes the connections of open visits whose quota has run out, and logs them as blocked.
+
+    A visit is let through while its quota has minutes left, and its own minute is one of
+    them; the connections are closed once a minute past that is counted.
+    */
+    pub fn cut_off(&self) {
+        let now = self.rules.local(self.clock.now());
+        let mut usage = self.usage.lock().unwrap();
+        for open in self.visits.lock().unwrap().values_mut() {
+            if open.streams.is_empty() || usage.used(&open.client.name(), &open.quota.category, now) <= open.quota.minutes {
+                continue;
+            }
+            for stream in open.streams.drain(..) {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            self.log(open.source, &open.client, Kind::Block, &open.host, format!("{} used up", open.quota));
+        }
+    }
+
+    /// Runs `cut_off` every so often, for as long as the proxy runs.
+    pub fn watch_quotas(&self) {
+        loop {
+            thread::sleep(QUOTA_CHECK);
+            self.cut_off();
+        }
+    }
+
+    /// Accepts proxy clients until the listener fails, handling each on its own thread.
+    pub fn serve(self: Arc<Self>, listener: TcpListener) {
+        self.accept(listener, Proxy::handle);
+    }
+
+    /// Accepts admin page clients until the listener fails.
+    pub fn serve_admin(self: Arc<Self>, listener: TcpListener) {
+        self.accept(listener, Proxy::handle_admin);
+    }
+
+    fn accept(self: Arc<Self>, listener: TcpListener, handle: fn(&Proxy, TcpStream) -> io::Result<()>) {
+        if let Ok(addr) = listener.local_addr() {
+            self.ports.lock().unwrap().push(addr.port());
+        }
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("accept failed: {}", e);
+                    continue;
+                }
+            };
+            let proxy = self.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                if let Err(e) = handle(&proxy, stream) {
+                    println!("{}: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    /// What the block page should say about `decision` on `host`.
+    fn details(&self, client: &Client, host: &str, decision: &Decision) -> Details {
+        let now = self.clock.now();
+        let (quota, used_up) = match self.rules.quota(client, host) {
+            Some(quota) => {
+                let used = self.usage.lock().unwrap().used(&client.name(), &quota.category, self.rules.local(now));
+                (page::quota_left(&quota.category, quota.minutes, used), used >= quota.minutes)
+            }
+            None => (String::new(), false),
+        };
+        let open = self.rules.next_allowed(client, host, now, used_up).map(|t| self.rules.local(t));
+        let asked = self.access.lock().unwrap().asked(&client.name(), host).map(|t| self.rules.local(t));
+        Details { host: host.to_string(), reason: decision.reason.clone(), until: page::until(self.rules.local(now), open), quota, asked }
+    }
+
+    /** Opens a connection to `host`, unless it's one of this proxy's own listeners.
+
+    Going through the proxy to the admin page would make every client look like the
+    proxy's own machine, which is always let in.
+    */
+    fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let upstream = connect(host, port)?;
+        let (peer, local) = (upstream.peer_addr()?, upstream.local_addr()?);
+        if (peer.ip().is_loopback() || peer.ip() == local.ip()) && self.ports.lock().unwrap().contains(&peer.port()) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "that's this proxy"));
+        }
+        Ok(upstream)
+    }
+
+    /// Reads one request from a client and answers it.
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let peer = self.client(stream.peer_addr()?.ip());
+        let mut client = BufReader::new(stream);
+        let Some(request) = Request::read(&mut client)? else {
+            return Ok(());
+        };
+        if request.method.eq_ignore_ascii_case("CONNECT") {
+            return self.tunnel(&peer, client, &request);
+        }
+        let Some((host, port, path)) = request.destination().or_else(|| request.host_destination()) else {
+            let mut stream = client.into_inner();
+            if let Some(asset) = page::asset(&request.target) {
+                return serve_asset(&mut stream, &request, asset);
+            }
+            return http::respond(&mut stream, 400, "Bad Request", "text/plain", b"This is a proxy; set it as your HTTP proxy.\n");
+        };
+        let mut stream = client.get_ref().try_clone()?;
+        let visit = match self.check(&peer, &host, Source::Proxy) {
+            Ok(visit) => visit,
+            Err(decision) => {
+                if let Some(asset) = page::asset(&path) {
+                    return serve_asset(&mut stream, &request, asset);
+                }
+                if path == page::REQUEST_PATH && request.method.eq_ignore_ascii_case("POST") {
+                    let form = http::read_form(&mut client, &request)?;
+                    let note = form.get("note").map_or("", |n| n.as_str());
+                    self.log(Source::Proxy, &peer, Kind::Ask, &host, note.to_string());
+                    self.access.lock().unwrap().ask(&peer.name(), &host, note, self.clock.now());
+                    // Back to the block page, which now says they're waiting.
+                    let back = request.header("referer").map_or_else(|| format!("http://{}/", request.header("host").unwrap_or(&host)), String::from);
+                    return http::redirect(&mut stream, &back);
+                }
+                let theme = page::theme(self.rules.theme(&peer)).unwrap();
+                let body = theme.render(&self.details(&peer, &host, &decision));
+                return blocked(&mut stream, &decision, "text/html; charset=utf-8", body.as_bytes());
+            }
+        };
+        let mut upstream = match self.connect(&host, port) {
+            Ok(upstream) => upstream,
+            Err(e) => return http::respond(&mut stream, 502, "Bad Gateway", "text/plain", format!("Couldn't reach {}: {}\n", host, e).as_bytes()),
+        };
+        visit.watch(&stream)?;
+        visit.watch(&upstream)?;
+        upstream.write_all(request.upstream_head(&host, port, &path).as_bytes())?;
+        // Send the body alongside reading the response, in case the server answers early.
+        let mut body: Box<dyn Read + Send> = match request.content_length() {
+            Some(length) => Box::new(client.take(length)),
+            None if request.header("transfer-encoding").is_some() => Box::new(client),
+            None => Box::new(io::empty()),
+        };
+        let mut to_upstream = upstream.try_clone()?;
+        let sender = thread::spawn(move || io::copy(&mut body, &mut to_upstream));
+        io::copy(&mut upstream, &mut stream)?;
+        let _ = stream.shutdown(Shutdown::Both);
+        let _ = upstream.shutdown(Shutdown::Both);
+        let _ = sender.join();
+        Ok(())
+    }
+
+    /** Answers the admin page: pending requests to approve or deny, and grants to revoke.
+
+    Only the proxy's own machine and the rules' `admin` clients get in. Forms post back
+    to `/approve`, `/deny` and `/revoke`, which redirect to the page again; a post from
+    another site's page is refused, so a page can't approve things behind a parent's back.
+    */
+    fn handle_admin(&self, stream: TcpStream) -> io::Result<()> {
+        let peer = self.client(stream.peer_addr()?.ip());
+        let mut client = BufReader::new(stream);
+        let Some(request) = Request::read(&mut client)? else {
+            return Ok(());
+        };
+        let mut stream = client.get_ref().try_clone()?;
+        let same_site = request.header("origin").is_none_or(|origin| origin.strip_prefix("http://") == request.header("host"));
+        if !self.rules.is_admin(&peer) || !same_site {
+            return http::respond(&mut stream, 403, "Forbidden", "text/plain", b"Only a parent's computer can use this page.\n");
+        }
+        let now = self.clock.now();
+        match (request.method.as_str(), request.target.as_str()) {
+            ("GET", "/") => {
+                let body = self.access.lock().unwrap().admin_page(|t| self.rules.local(t));
+                http::respond(&mut stream, 200, "OK", "text/html; charset=utf-8", body.as_bytes())
+            }
+            ("POST", action @ ("/approve" | "/deny" | "/revoke")) => {
+                let form = http::read_form(&mut client, &request)?;
+                let id = form.get("id").and_then(|id| id.parse().ok());
+                let field = |name| form.get(name).map_or("", |v| v.as_str());
+                let mut access = self.access.lock().unwrap();
+                match (action, id, Approval::parse(field("for"))) {
+                    ("/approve", Some(id), Some(approval)) => {
+                        if let Some(grant) = access.approve(id, approval, now) {
+                            let (device, host, detail) = (grant.device.clone(), grant.host.clone(), field("for").to_string());
+                            self.log.write(&Event { time: self.rules.local(now), source: Source::Proxy, device, kind: Kind::Grant, host, detail });
+                        }
+                    }
+                    ("/deny", Some(id), _) => access.deny(id),
+                    ("/revoke", _, _) => access.revoke(field("device"), field("host")),
+                    _ => return http::respond(&mut stream, 400, "Bad Request", "text/plain", b"Missing or bad form fields.\n"),
+                }
+                http::redirect(&mut stream, "/")
+            }
+            _ => http::respond(&mut stream, 404, "Not Found", "text/plain", b"Not found.\n"),
+        }
+    }
+
+    /** Opens a `CONNECT` tunnel and relays bytes both ways until either side closes.
+
+    A blocked tunnel host is refused with a 403 before anything is connected. Otherwise
+    the tunnel is opened and the first thing through it is checked: a ClientHello naming a
+    blocked server gets a fatal TLS alert instead of being passed on, so the browser
+    shows a connection error and the blocked server never hears from it. So does a TLS
+    handshake that doesn't get a whole hello out within `hello_timeout`, since its name
+    can't be checked. Traffic that isn't TLS, or nothing at all within `hello_timeout` as
+    when the server speaks first, is passed on unchecked, since the tunnel's host was
+    already allowed.
+    */
+    fn tunnel(&self, peer: &Client, mut client: BufReader<TcpStream>, request: &Request) -> io::Result<()> {
+        let mut stream = client.get_ref().try_clone()?;
+        let Some((host, port)) = http::split_host_port(&request.target, 443) else {
+            return http::respond(&mut stream, 400, "Bad Request", "text/plain", b"CONNECT needs host:port\n");
+        };
+        let visit = match self.check(peer, &host, Source::Proxy) {
+            Ok(visit) => visit,
+            Err(decision) => return blocked(&mut stream, &decision, "text/plain", format!("Blocked by {}\n", decision.reason).as_bytes()),
+        };
+        let mut upstream = match self.connect(&host, port) {
+            Ok(upstream) => upstream,
+            Err(e) => return http::respond(&mut stream, 502, "Bad Gateway", "text/plain", format!("Couldn't reach {}: {}\n", host, e).as_bytes()),
+        };
+        visit.watch(&stream)?;
+        visit.watch(&upstream)?;
+        stream.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?;
+        stream.set_read_timeout(Some(self.hello_timeout))?;
+        let (hello, server_name) = match tls::read_client_hello(&mut client) {
+            Ok(hello) => hello,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                self.log(Source::Proxy, peer, Kind::Block, &host, e.to_string());
+                stream.write_all(&tls::ACCESS_DENIED)?;
+                let _ = stream.shutdown(Shutdown::Both);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        stream.set_read_timeout(None)?;
+        // Counted as well as the tunnel's host, in case only the name is under a quota.
+        let named_visit = match server_name.filter(|name| *name != host).map(|name| self.check(peer, &name, Source::Proxy)).transpose() {
+            Ok(visit) => visit,
+            Err(_) => {
+                stream.write_all(&tls::ACCESS_DENIED)?;
+                let _ = stream.shutdown(Shutdown::Both);
+                return Ok(());
+            }
+        };
+        if let Some(named_visit) = &named_visit {
+            named_visit.watch(&stream)?;
+            named_visit.watch(&upstream)?;
+        }
+        upstream.write_all(&hello)?;
+        let mut to_upstream = upstream.try_clone()?;
+        let sender = thread::spawn(move || {
+            let result = io::copy(&mut client, &mut to_upstream);
+            let _ = to_upstream.shutdown(Shutdown::Write);
+            result
+        });
+        io::copy(&mut upstream, &mut stream)?;
+        let _ = stream.shutdown(Shutdown::Write);
+        let _ = sender.join();
+        Ok(())
+    }
+}
+
+/// A 403 saying which rule blocked the request, never cached since the block may lift.
+fn blocked(stream: &mut TcpStream, decision: &Decision, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 403 Forbidden\r\nX-Blocked-By: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        decision.reason,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// A theme asset, which browsers may keep for a day and then check with its tag.
+fn serve_asset(stream: &mut TcpStream, request: &Request, asset: &Asset) -> io::Result<()> {
+    let etag = page::etag(asset);
+    if request.header("if-none-match") == Some(etag.as_str()) {
+        write!(stream, "HTTP/1.1 304 Not Modified\r\nETag: {}\r\nConnection: close\r\n\r\n", etag)?;
+        return stream.flush();
+    }
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: public, max-age=86400\r\nETag: {}\r\nConnection: close\r\n\r\n",
+        asset.content_type,
+        asset.bytes.len(),
+        etag
+    )?;
+    stream.write_all(asset.bytes)?;
+    stream.flush()
+}
+
+/// Opens a connection to the first address `host` resolves to that answers.
+pub fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last = io::Error::new(io::ErrorKind::NotFound, "no addresses");
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = e,
+        }
+    }
+    Err(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::sync::mpsc;
+
+    use crate::schedule::{FixedClock, SystemClock};
+
+    /// A stand-in destination that answers one request with `hello` and reports what it got.
+    fn upstream() -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let request = Request::read(&mut reader).unwrap().unwrap();
+            let mut body = vec![0; request.content_length().unwrap_or(0) as usize];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = reader.into_inner();
+            http::respond(&mut stream, 200, "OK", "text/plain", b"hello").unwrap();
+            let headers: String = request.headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect();
+            tx.send(format!("{} {} {}\r\n{}\r\n{}", request.method, request.target, request.version, headers, String::from_utf8(body).unwrap())).unwrap();
+        });
+        (port, rx)
+    }
+
+    /// A stand-in destination that echoes whatever it's sent back, then closes.
+    fn echo() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = stream.try_clone().unwrap();
+                io::copy(&mut reader, &mut stream).unwrap();
+            }
+        });
+        port
+    }
+
+    fn start(proxy: Proxy) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || Arc::new(proxy).serve(listener));
+        port
+    }
+
+    fn send_bytes(port: u16, request: &str) -> Vec<u8> {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        response
+    }
+
+    fn send(port: u16, request: &str) -> String {
+        String::from_utf8(send_bytes(port, request)).unwrap()
+    }
+
+    fn proxy() -> Proxy {
+        Proxy::new(Rules::parse("deny example.com *.example.com").unwrap(), Box::new(SystemClock))
+    }
+
+    #[test]
+    fn test_forward() {
+        let (upstream_port, received) = upstream();
+        let port = start(proxy());
+        let response = send(
+            port,
+            &format!("POST http://127.0.0.1:{0}/form HTTP/1.1\r\nHost: www.example.com\r\nProxy-Connection: keep-alive\r\nContent-Length: 4\r\n\r\na=bc", upstream_port),
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nhello"));
+        let request = received.recv().unwrap();
+        let mut lines = request.as_bytes().lines().map(|l| l.unwrap());
+        assert_eq!(lines.next().unwrap(), "POST /form HTTP/1.1");
+        // The host that was checked, not the blocked one the client named.
+        assert_eq!(lines.next().unwrap(), format!("Host: 127.0.0.1:{}", upstream_port));
+        assert!(!request.contains("example.com"));
+        assert!(!request.contains("Proxy-Connection"));
+        assert!(request.ends_with("\r\n\r\na=bc"));
+    }
+
+    #[test]
+    fn test_block() {
+        let port = start(proxy());
+        let response = send(port, "GET http://www.example.com/games HTTP/1.1\r\nHost: www.example.com\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\nX-Blocked-By: line 1: deny *.example.com\r\n"));
+        assert!(response.contains("POOP ALERT"));
+        assert!(response.contains("<b>www.example.com</b> is blocked."));
+        assert!(response.contains("It stays blocked until the rules change."));
+        // The background comes separately and can be cached.
+        let path = "/.proxy/themes/classic/background.webp";
+        assert!(response.contains(path));
+        let image = send_bytes(port, &format!("GET http://www.example.com{} HTTP/1.1\r\n\r\n", path));
+        let etag = page::etag(page::asset(path).unwrap());
+        assert!(image.starts_with(b"HTTP/1.1 200 OK\r\nContent-Type: image/webp\r\n"));
+        assert!(image.ends_with(page::asset(path).unwrap().bytes));
+        let cached = send(port, &format!("GET {} HTTP/1.1\r\nIf-None-Match: {}\r\n\r\n", path, etag));
+        assert!(cached.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        // Rules for this client in particular.
+        let local = start(Proxy::new(Rules::parse("group here 127.0.0.1\ndefault deny").unwrap(), Box::new(SystemClock)));
+        let response = send(local, "GET http://example.org/ HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\nX-Blocked-By: default deny for here\r\n"));
+        // Sent straight here, as by a device whose DNS points blocked names at the proxy.
+        let response = send(port, "GET /games HTTP/1.1\r\nHost: www.example.com\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\nX-Blocked-By: line 1: deny *.example.com\r\n"));
+        let (upstream_port, received) = upstream();
+        let response = send(port, &format!("GET /homework HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n", upstream_port));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(received.recv().unwrap().starts_with("GET /homework HTTP/1.1\r\n"));
+        // Without a host there's nothing to go on.
+        assert!(send(port, "GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn test_schedule() {
+        let rules = Rules::parse("timezone Europe/London\nschedule bedtime daily 21:00-07:00\ndeny * during bedtime").unwrap();
+        // 21:30 in London, on summer time.
+        let night = start(Proxy::new(rules.clone(), Box::new(FixedClock::new("2024-06-03T20:30:00Z".parse().unwrap()))));
+        let response = send(night, "GET http://127.0.0.1:9/ HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\nX-Blocked-By: line 3: deny * during bedtime\r\n"));
+        assert!(response.contains("Open again tomorrow at 07:00."));
+        let morning = start(Proxy::new(rules, Box::new(FixedClock::new("2024-06-03T06:00:00Z".parse().unwrap()))));
+        let response = send(morning, &format!("GET http://127.0.0.1:{}/ HTTP/1.1\r\n\r\n", upstream().0));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn test_quota() {
+        let path = std::env::temp_dir().join(format!("proxy-quota-{}.txt", std::process::id()));
+        // 59 minutes of video already used today, from before a restart.
+        std::fs::write(&path, "2024-06-03\n127.0.0.1 video 480-538\n").unwrap();
+        let rules = Rules::parse("timezone UTC\ncategory video 127.0.0.1\nquota video 1h").unwrap();
+        let clock = Box::new(FixedClock::new("2024-06-03T12:00:00Z".parse().unwrap()));
+        let log_path = std::env::temp_dir().join(format!("proxy-activity-{}.log", std::process::id()));
+        let log = Log::new(log_path.clone());
+        let port = start(Proxy { usage: Mutex::new(Usage::load(path.clone()).unwrap()), log, ..Proxy::new(rules, clock) });
+        // The last minute gets used, and saved.
+        let response = send(port, &format!("GET http://127.0.0.1:{}/ HTTP/1.1\r\n\r\n", upstream().0));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let response = send(port, "GET http://127.0.0.1:9/ HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\nX-Blocked-By: line 3: quota video 1h used up\r\n"));
+        assert!(response.contains("No video time left today (1h a day)."));
+        assert!(response.contains("Open again tomorrow at 00:00."));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "2024-06-03\n127.0.0.1 video 480-538,720\n");
+        std::fs::remove_file(&path).unwrap();
+        let logged: Vec<_> = crate::log::read(&log_path).unwrap().into_iter().map(|e| format!("{} {} {} {}", e.device, e.kind, e.host, e.detail)).collect();
+        assert!(logged.contains(&String::from("127.0.0.1 allow 127.0.0.1 default allow")));
+        assert!(logged.contains(&String::from("127.0.0.1 used 127.0.0.1 video 720-720")));
+        assert!(logged.contains(&String::from("127.0.0.1 block 127.0.0.1 line 3: quota video 1h used up")));
+        std::fs::remove_file(&log_path).unwrap();
+    }
+
+    #[test]
+    fn test_quota_cut_off() {
+        let rules = Rules::parse("timezone UTC\ncategory video 127.0.0.1\nquota video 2m").unwrap();
+        let clock = Arc::new(FixedClock::new("2024-06-03T12:00:00Z".parse().unwrap()));
+        let proxy = Arc::new(Proxy::new(rules, Box::new(clock.clone())));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let serving = proxy.clone();
+        thread::spawn(move || serving.serve(listener));
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(stream, "CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\n", echo()).unwrap();
+        let established = b"HTTP/1.1 200 Connection Established\r\n\r\n";
+        let mut echoed = vec![0; established.len() + 5];
+        stream.write_all(b"hello").unwrap();
+        stream.read_exact(&mut echoed).unwrap();
+        assert_eq!(echoed, [&established[..], b"hello"].concat());
+        // Its second minute is still within the quota.
+        *clock.0.lock().unwrap() += chrono::TimeDelta::minutes(1);
+        proxy.cut_off();
+        stream.write_all(b"again").unwrap();
+        stream.read_exact(&mut echoed[..5]).unwrap();
+        assert_eq!(&echoed[..5], b"again");
+        // The third isn't, so the tunnel is closed.
+        *clock.0.lock().unwrap() += chrono::TimeDelta::minutes(1);
+        proxy.cut_off();
+        let mut rest = vec![];
+        stream.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+        let response = send(port, "GET http://127.0.0.1:9/ HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\nX-Blocked-By: line 3: quota video 2m used up\r\n"));
+    }
+
+    #[test]
+    fn test_access_request() {
+        let log_path = std::env::temp_dir().join(format!("proxy-access-{}.log", std::process::id()));
+        let proxy = Arc::new(Proxy { log: Log::new(log_path.clone()), ..Proxy::new(Rules::parse("deny 127.0.0.1").unwrap(), Box::new(SystemClock)) });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let admin_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (port, admin) = (listener.local_addr().unwrap().port(), admin_listener.local_addr().unwrap().port());
+        let admin_proxy = proxy.clone();
+        thread::spawn(move || admin_proxy.serve_admin(admin_listener));
+        thread::spawn(move || proxy.serve(listener));
+        let (upstream_port, _) = upstream();
+        let url = format!("http://127.0.0.1:{}/homework", upstream_port);
+        assert!(send(port, &format!("GET {} HTTP/1.1\r\n\r\n", url)).contains("Ask for access"));
+        let response = send(port, &format!("POST http://127.0.0.1:{}{} HTTP/1.1\r\nReferer: {}\r\nContent-Length: 17\r\n\r\nnote=for+homework", upstream_port, page::REQUEST_PATH, url));
+        assert!(response.starts_with(&format!("HTTP/1.1 303 See Other\r\nLocation: {}\r\n", url)));
+        assert!(send(port, &format!("GET {} HTTP/1.1\r\n\r\n", url)).contains("Waiting for a parent."));
+        let page = send(admin, "GET / HTTP/1.1\r\n\r\n");
+        assert!(page.contains("<td>127.0.0.1</td><td>127.0.0.1</td><td>for homework</td>"));
+        // Another site's page can't approve it.
+        let approve = "POST /approve HTTP/1.1\r\nHost: 127.0.0.1\r\nOrigin: http://example.com\r\nContent-Length: 12\r\n\r\nid=1&for=day";
+        assert!(send(admin, approve).starts_with("HTTP/1.1 403"));
+        assert!(send(admin, &approve.replace("example.com", "127.0.0.1")).starts_with("HTTP/1.1 303 See Other\r\nLocation: /\r\n"));
+        assert!(send(port, &format!("GET {} HTTP/1.1\r\n\r\n", url)).starts_with("HTTP/1.1 200 OK\r\n"));
+        // Nor can the admin page be reached through the proxy, where everyone would look like this machine.
+        let response = send(port, &format!("GET http://127.0.0.1:{}/ HTTP/1.1\r\n\r\n", admin));
+        assert!(response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+        assert!(response.contains("that's this proxy"));
+        let logged: Vec<_> = crate::log::read(&log_path).unwrap().into_iter().map(|e| format!("{} {} {} {}", e.device, e.kind, e.host, e.detail)).collect();
+        assert!(logged.contains(&String::from("127.0.0.1 ask 127.0.0.1 for homework")));
+        assert!(logged.contains(&String::from("127.0.0.1 grant 127.0.0.1 day")));
+        std::fs::remove_file(&log_path).unwrap();
+    }
+
+    #[test]
+    fn test_unreachable() {
+        // Nothing listens on a port that was just freed.
+        let free = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let port = start(Proxy::new(Rules::allow_all(), Box::new(SystemClock)));
+        let response = send(port, &format!("GET http://127.0.0.1:{}/ HTTP/1.1\r\n\r\n", free));
+        assert!(response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+    }
+
+    /// Opens a tunnel to the echo server, sends `data` and half-closes, returning everything that comes back.
+    fn tunnel(port: u16, target: &str, data: &[u8]) -> Vec<u8> {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target).unwrap();
+        stream.write_all(data).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_tunnel() {
+        let echo_port = echo();
+        let port = start(proxy());
+        let target = format!("127.0.0.1:{}", echo_port);
+        let established = b"HTTP/1.1 200 Connection Established\r\n\r\n".to_vec();
+        // An allowed name goes through byte for byte, along with whatever follows it.
+        let data = [tls::client_hello("allowed.test", 2), (0..=255).collect()].concat();
+        assert_eq!(tunnel(port, &target, &data), [established.clone(), data].concat());
+        // Not TLS at all: decided on the tunnel host alone.
+        assert_eq!(tunnel(port, &target, b"SSH-2.0-x\r\n"), [established.clone(), b"SSH-2.0-x\r\n".to_vec()].concat());
+        // A blocked name behind an allowed tunnel host gets an alert and nothing reaches the server.
+        let data = tls::client_hello("video.example.com", 1);
+        assert_eq!(tunnel(port, &target, &data), [established, tls::ACCESS_DENIED.to_vec()].concat());
+        // A blocked tunnel host is refused outright.
+        let response = tunnel(port, "www.example.com:443", &[]);
+        assert!(response.starts_with(b"HTTP/1.1 403 Forbidden\r\n"));
+    }
+
+    #[test]
+    fn test_tunnel_hello_timeout() {
+        let echo_port = echo();
+        let port = start(Proxy { hello_timeout: Duration::from_millis(100), ..proxy() });
+        let established = b"HTTP/1.1 200 Connection Established\r\n\r\n".to_vec();
+        let open = || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            write!(stream, "CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\n", echo_port).unwrap();
+            stream
+        };
+        // A hello too slow to be checked is refused, rather than letting its name through unread.
+        let mut stream = open();
+        stream.write_all(&tls::client_hello("video.example.com", 1)[..3]).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        assert_eq!(response, [established.clone(), tls::ACCESS_DENIED.to_vec()].concat());
+        // Saying nothing at first, as when the server speaks first, is let through.
+        let mut stream = open();
+        thread::sleep(Duration::from_millis(300));
+        stream.write_all(b"hello").unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        assert_eq!(response, [established, b"hello".to_vec()].concat());
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+
+/** Minutes of use per device and category for one day, optionally kept in a file.
+
+A device uses a category during every minute it has a request or an open connection to
+one of its hosts, so ten requests in the same minute count once and an hour-long video
+stream counts as an hour. Usage starts over when the day (in the rules' timezone)
+changes. With a file, every finished connection is written to it straight away, so a
+restart picks up where it left off.
+
+The file has the day on its first line, then one `DEVICE CATEGORY MINUTES` line per
+device and category, where minutes are ranges of minutes since midnight like `540-599,605`.
+*/
+#[derive(Debug, Default)]
+pub struct Usage {
+    pub path: Option<PathBuf>,
+    day: Option<NaiveDate>,
+    minutes: HashMap<(String, String), BTreeSet<u32>>,
+    /// Connections still open: who, what, and the minute they started.
+    open: HashMap<u64, (String, String, u32)>,
+    next_id: u64,
+}
+
+impl Usage {
+    /// Reads usage from `path`, or starts afresh if it doesn't exist yet.
+    pub fn load(path: PathBuf) -> Result<Usage, String> {
+        let mut usage = Usage { path: Some(path.clone()), ..Usage::default() };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(usage),
+            Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+        };
+        let bad = |line: &str| format!("{}: can't read {:?}", path.display(), line);
+        let mut lines = text.lines();
+        if let Some(day) = lines.next() {
+            usage.day = Some(day.parse().map_err(|_| bad(day))?);
+        }
+        for line in lines {
+            let [device, category, ranges] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(bad(line));
+            };
+            let minutes = parse_ranges(ranges).ok_or_else(|| bad(line))?;
+            usage.minutes.entry((device.to_string(), category.to_string())).or_default().extend(minutes);
+        }
+        Ok(usage)
+    }
+
+    /// Writes the finished usage out, if there's a file to keep it in.
+    fn save(&self) {
+        let (Some(path), Some(day)) = (&self.path, self.day) else {
+            return;
+        };
+        let mut text = format!("{}\n", day);
+        let mut keys: Vec<_> = self.minutes.keys().collect();
+        keys.sort();
+        for key in keys {
+            text.push_str(&format!("{} {} {}\n", key.0, key.1, ranges(&self.minutes[key])));
+        }
+        // Write then rename, so a crash never leaves half a file.
+        let tmp = path.with_extension("tmp");
+        if let Err(e) = std::fs::write(&tmp, text).and_then(|_| std::fs::rename(&tmp, path)) {
+            println!("couldn't save usage to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Starts the day over if `now` is on a different one.
+    fn roll(&mut self, now: NaiveDateTime) {
+        if self.day != Some(now.date()) {
+            self.day = Some(now.date());
+            self.minutes.clear();
+            for (_, _, start) in self.open.values_mut() {
+                *start = 0;
+            }
+        }
+    }
+
+    /// Minutes `device` has spent on `category` today, up to and including `now`.
+    pub fn used(&mut self, device: &str, category: &str, now: NaiveDateTime) -> u32 {
+        self.roll(now);
+        let mut minutes = self.minutes.get(&(device.to_string(), category.to_string())).cloned().unwrap_or_default();
+        for (d, c, start) in self.open.values() {
+            if d == device && c == category {
+                minutes.extend(*start..=minute(now));
+            }
+        }
+        minutes.len() as u32
+    }
+
+    /// Notes a connection starting at `now`, returning an id to `close` it with.
+    pub fn open(&mut self, device: &str, category: &str, now: NaiveDateTime) -> u64 {
+        self.roll(now);
+        self.next_id += 1;
+        self.open.insert(self.next_id, (device.to_string(), category.to_string(), minute(now)));
+        self.next_id
+    }
+
+    /// Counts a connection from `open` as having used every minute until `now`, and saves, returning those minutes.
+    pub fn close(&mut self, id: u64, now: NaiveDateTime) -> Option<RangeInclusive<u32>> {
+        self.roll(now);
+        let (device, category, start) = self.open.remove(&id)?;
+        self.minutes.entry((device, category)).or_default().extend(start..=minute(now));
+        self.save();
+        Some(start..=minute(now))
+    }
+}
+
+fn minute(time: NaiveDateTime) -> u32 {
+    time.hour() * 60 + time.minute()
+}
+
+/// `1,2,3,7` as `1-3,7`.
+pub fn ranges(minutes: &BTreeSet<u32>) -> String {
+    let mut out: Vec<(u32, u32)> = vec![];
+    for &m in minutes {
+        match out.last_mut() {
+            Some((_, last)) if *last + 1 == m => *last = m,
+            _ => out.push((m, m)),
+        }
+    }
+    out.iter().map(|(a, b)| if a == b { a.to_string() } else { format!("{}-{}", a, b) }).collect::<Vec<_>>().join(",")
+}
+
+/// `1-3,7` as `1,2,3,7`.
+pub fn parse_ranges(text: &str) -> Option<BTreeSet<u32>> {
+    let mut minutes = BTreeSet::new();
+    for range in text.split(',') {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let (first, last): (u32, u32) = (first.parse().ok()?, last.parse().ok()?);
+        minutes.extend(first..=last);
+    }
+    Some(minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap().and_hms_opt(hour, minute, 30).unwrap()
+    }
+
+    #[test]
+    fn test_usage() {
+        let mut usage = Usage::default();
+        // Requests in the same minute count once.
+        for _ in 0..3 {
+            let id = usage.open("tablet", "video", at(3, 9, 0));
+            usage.close(id, at(3, 9, 0));
+        }
+        assert_eq!(usage.used("tablet", "video", at(3, 9, 5)), 1);
+        // An open connection counts up to now, and overlapping ones don't count twice.
+        let stream = usage.open("tablet", "video", at(3, 10, 0));
+        let other = usage.open("tablet", "video", at(3, 10, 5));
+        assert_eq!(usage.used("tablet", "video", at(3, 10, 9)), 11);
+        usage.close(other, at(3, 10, 20));
+        assert_eq!(usage.close(stream, at(3, 10, 29)), Some(600..=629));
+        assert_eq!(usage.close(stream, at(3, 10, 29)), None);
+        assert_eq!(usage.used("tablet", "video", at(3, 11, 0)), 31);
+        assert_eq!(usage.used("tablet", "games", at(3, 11, 0)), 0);
+        assert_eq!(usage.used("phone", "video", at(3, 11, 0)), 0);
+        // A new day starts from nothing.
+        assert_eq!(usage.used("tablet", "video", at(4, 0, 0)), 0);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("proxy-usage-{}.txt", std::process::id()));
+        let mut usage = Usage { path: Some(path.clone()), ..Usage::default() };
+        let id = usage.open("tablet", "video", at(3, 9, 0));
+        usage.close(id, at(3, 9, 4));
+        let id = usage.open("192.168.1.30", "games", at(3, 9, 10));
+        usage.close(id, at(3, 9, 10));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "2024-06-03\n192.168.1.30 games 550\ntablet video 540-544\n");
+        let mut loaded = Usage::load(path.clone()).unwrap();
+        assert_eq!(loaded.used("tablet", "video", at(3, 12, 0)), 5);
+        assert_eq!(loaded.used("tablet", "video", at(4, 12, 0)), 0);
+        std::fs::write(&path, "2024-06-03\ntablet video soon\n").unwrap();
+        assert!(Usage::load(path.clone()).unwrap_err().ends_with("can't read \"tablet video soon\""));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
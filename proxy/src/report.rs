@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::NaiveDate;
+
+use crate::log::{Event, Kind};
+use crate::page::escape;
+use crate::quota;
+use crate::rules::duration;
+
+/// How many of a device's hosts the HTML report lists; the CSV has them all.
+const TOP_HOSTS: usize = 10;
+
+/// How often a device asked for a host, and how many of those times it was blocked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostCount {
+    pub host: String,
+    pub requests: u32,
+    pub blocked: u32,
+}
+
+/// One device's day.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceDay {
+    pub device: String,
+    /// Most asked for first.
+    pub hosts: Vec<HostCount>,
+    /// Each blocked host and the rule in the way, with how often; most often first.
+    pub blocks: Vec<(String, String, u32)>,
+    /// Minutes counted against each quota category.
+    pub quotas: Vec<(String, u32)>,
+}
+
+#[derive(Default)]
+struct Tally<'a> {
+    hosts: HashMap<&'a str, (u32, u32)>,
+    blocks: HashMap<(&'a str, &'a str), u32>,
+    minutes: BTreeMap<&'a str, BTreeSet<u32>>,
+}
+
+/** Sums up each device's `day` from the activity log, in order of device name.
+
+Proxy requests and DNS lookups both count as asking for a host, so a device using both
+counts twice. Quota time is every minute counted against a category, as in `quota::Usage`.
+*/
+pub fn summarize(events: &[Event], day: NaiveDate) -> Vec<DeviceDay> {
+    let mut devices: BTreeMap<&str, Tally> = BTreeMap::new();
+    for event in events.iter().filter(|e| e.time.date() == day) {
+        let tally = devices.entry(&event.device).or_default();
+        match event.kind {
+            Kind::Allow | Kind::Block => {
+                let count = tally.hosts.entry(&event.host).or_default();
+                count.0 += 1;
+                if event.kind == Kind::Block {
+                    count.1 += 1;
+                    *tally.blocks.entry((&event.host, &event.detail)).or_default() += 1;
+                }
+            }
+            Kind::Used => {
+                if let Some((category, Some(minutes))) = event.detail.split_once(' ').map(|(c, m)| (c, quota::parse_ranges(m))) {
+                    tally.minutes.entry(category).or_default().extend(minutes);
+                }
+            }
+            Kind::SafeSearch | Kind::Ask | Kind::Grant => {}
+        }
+    }
+    devices
+        .into_iter()
+        .map(|(device, tally)| {
+            let mut hosts: Vec<_> = tally.hosts.into_iter().map(|(host, (requests, blocked))| HostCount { host: host.to_string(), requests, blocked }).collect();
+            hosts.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.host.cmp(&b.host)));
+            let mut blocks: Vec<_> = tally.blocks.into_iter().map(|((host, reason), n)| (host.to_string(), reason.to_string(), n)).collect();
+            blocks.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (&a.0, &a.1).cmp(&(&b.0, &b.1))));
+            let quotas = tally.minutes.into_iter().map(|(category, minutes)| (category.to_string(), minutes.len() as u32)).collect();
+            DeviceDay { device: device.to_string(), hosts, blocks, quotas }
+        })
+        .collect()
+}
+
+/** The report as CSV, a `device,type,name,count,detail` row for each thing counted.
+
+`domain` rows count requests, with how many were blocked as the detail; `block` rows
+count blocks of a host by one rule, which is the detail; `quota` rows count minutes.
+*/
+pub fn csv(days: &[DeviceDay]) -> String {
+    let mut out = String::from("device,type,name,count,detail\n");
+    let mut row = |fields: [&str; 5]| {
+        let quoted: Vec<_> = fields.iter().map(|f| if f.contains([',', '"', '\n']) { format!("\"{}\"", f.replace('"', "\"\"")) } else { f.to_string() }).collect();
+        out.push_str(&quoted.join(","));
+        out.push('\n');
+    };
+    for day in days {
+        for host in &day.hosts {
+            row([&day.device, "domain", &host.host, &host.requests.to_string(), &host.blocked.to_string()]);
+        }
+        for (host, reason, n) in &day.blocks {
+            row([&day.device, "block", host, &n.to_string(), reason]);
+        }
+        for (category, minutes) in &day.quotas {
+            row([&day.device, "quota", category, &minutes.to_string(), &duration(*minutes)]);
+        }
+    }
+    out
+}
+
+/// The report as a page in the block page's style, with each device's top hosts, blocks and quota time.
+pub fn html(day: NaiveDate, days: &[DeviceDay]) -> String {
+    let table = |head: &str, rows: Vec<String>| {
+        let columns = head.matches("<th>").count();
+        let rows = if rows.is_empty() { format!("<tr><td colspan=\"{}\">None</td></tr>\n", columns) } else { rows.concat() };
+        format!("<table>\n<tr>{}</tr>\n{}</table>\n", head, rows)
+    };
+    let devices: String = days
+        .iter()
+        .map(|d| {
+            let hosts = d.hosts.iter().take(TOP_HOSTS).map(|h| format!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n", escape(&h.host), h.requests, h.blocked)).collect();
+            let blocks = d.blocks.iter().map(|(host, reason, n)| format!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n", escape(host), escape(reason), n)).collect();
+            let quotas = d.quotas.iter().map(|(category, minutes)| format!("<tr><td>{}</td><td>{}</td></tr>\n", escape(category), duration(*minutes))).collect();
+            format!(
+                "<div class=\"device\">\n<h2>{}</h2>\n{}{}{}</div>\n",
+                escape(&d.device),
+                table("<th>Top hosts</th><th>Requests</th><th>Blocked</th>", hosts),
+                table("<th>Blocked</th><th>By</th><th>Times</th>", blocks),
+                table("<th>Quota</th><th>Time used</th>", quotas)
+            )
+        })
+        .collect();
+    let devices = if devices.is_empty() { String::from("<p>Nothing logged.</p>") } else { devices };
+    include_str!("../report.html").replace("{{day}}", &day.to_string()).replace("{{devices}}", &devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<Event> {
+        [
+            "2024-06-02T23:59:00\tproxy\ttablet\tblock\twww.roblox.com\tline 3: deny games",
+            "2024-06-03T16:00:00\tproxy\ttablet\tallow\twww.khanacademy.org\tline 5: allow school",
+            "2024-06-03T16:01:00\tproxy\ttablet\tblock\twww.roblox.com\tline 3: deny games",
+            "2024-06-03T16:01:30\tdns\ttablet\tblock\twww.roblox.com\tline 3: deny games",
+            "2024-06-03T16:02:00\tproxy\ttablet\tallow\twww.youtube.com\tdefault allow",
+            "2024-06-03T16:02:00\tproxy\ttablet\tblock\twww.youtube.com\tline 9: quota video 1h used up",
+            "2024-06-03T16:10:00\tproxy\ttablet\tused\twww.youtube.com\tvideo 960-965",
+            "2024-06-03T16:10:00\tdns\ttablet\tused\tm.youtube.com\tvideo 965-970",
+            "2024-06-03T16:10:00\tdns\ttablet\tsafesearch\twww.google.com\tforcesafesearch.google.com",
+            "2024-06-03T17:00:00\tdns\tlaptop\tallow\t\"quoted\",host\tdefault allow",
+        ]
+        .iter()
+        .map(|line| Event::parse(line).unwrap())
+        .collect()
+    }
+
+    fn day() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 3).unwrap()
+    }
+
+    #[test]
+    fn test_summarize() {
+        let days = summarize(&events(), day());
+        assert_eq!(days.len(), 2);
+        let tablet = &days[1];
+        assert_eq!(tablet.device, "tablet");
+        assert_eq!(tablet.hosts[0], HostCount { host: String::from("www.roblox.com"), requests: 2, blocked: 2 });
+        assert_eq!(tablet.hosts[1], HostCount { host: String::from("www.youtube.com"), requests: 2, blocked: 1 });
+        assert_eq!(tablet.blocks[0], (String::from("www.roblox.com"), String::from("line 3: deny games"), 2));
+        assert_eq!(tablet.quotas, [(String::from("video"), 11)]);
+        assert!(summarize(&events(), NaiveDate::from_ymd_opt(2024, 6, 4).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_csv_and_html() {
+        let days = summarize(&events(), day());
+        let csv = csv(&days);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[1], "laptop,domain,\"\"\"quoted\"\",host\",1,0");
+        assert!(lines.contains(&"tablet,block,www.youtube.com,1,line 9: quota video 1h used up"));
+        assert_eq!(lines.last(), Some(&"tablet,quota,video,11,11m"));
+        let page = html(day(), &days);
+        assert!(page.contains("<title>Activity on 2024-06-03</title>"));
+        assert!(page.contains("<h2>laptop</h2>"));
+        assert!(page.contains("&quot;quoted&quot;,host"));
+        assert!(page.contains("<tr><td>video</td><td>11m</td></tr>"));
+        assert!(html(day(), &[]).contains("Nothing logged."));
+    }
+}
@@ -0,0 +1,694 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, DurationRound, Local, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::devices::{self, Client, Device};
+use crate::page;
+use crate::schedule::{Schedule, Window};
+
+/// Whether a rule lets a request through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+impl Action {
+    fn parse(word: &str) -> Option<Action> {
+        match word {
+            "allow" => Some(Action::Allow),
+            "deny" => Some(Action::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+        })
+    }
+}
+
+/// A host name to match: `example.com` exactly, `*.example.com` for anything under it, or `*` for everything.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Exact(String),
+    Subdomains(String),
+    Any,
+}
+
+impl Pattern {
+    fn parse(word: &str) -> Result<Pattern, String> {
+        let word = word.trim_end_matches('.').to_ascii_lowercase();
+        if word == "*" {
+            return Ok(Pattern::Any);
+        }
+        let pattern = match word.strip_prefix("*.") {
+            Some(domain) => Pattern::Subdomains(domain.to_string()),
+            None => Pattern::Exact(word.clone()),
+        };
+        match &pattern {
+            Pattern::Exact(host) | Pattern::Subdomains(host) if host.is_empty() || host.contains('*') => Err(format!("bad host pattern {}", word)),
+            _ => Ok(pattern),
+        }
+    }
+
+    /// Whether `host`, already lowercased without a trailing dot, matches.
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            Pattern::Exact(exact) => host == exact,
+            Pattern::Subdomains(domain) => host.strip_suffix(domain.as_str()).is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+            Pattern::Any => true,
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Exact(host) => f.write_str(host),
+            Pattern::Subdomains(domain) => write!(f, "*.{}", domain),
+            Pattern::Any => f.write_str("*"),
+        }
+    }
+}
+
+/// What a rule applies to: one host pattern, or every pattern in a named category.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Host(Pattern),
+    Category(String),
+}
+
+/// One target of an `allow` or `deny` line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    /// Line of the rule file it came from, for saying which rule matched.
+    pub line: usize,
+    pub action: Action,
+    pub target: Target,
+    /// The schedule the rule only applies during, if any.
+    pub during: Option<String>,
+}
+
+/// A daily allowance of time on a category's hosts, for each device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub line: usize,
+    pub category: String,
+    pub minutes: u32,
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: quota {} {}", self.line, self.category, duration(self.minutes))
+    }
+}
+
+/// The rules for everyone, or for one group of clients: checked in order, the first match wins.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleSet {
+    /// What happens when no rule matches; a group without one falls back to the global default.
+    pub default: Option<Action>,
+    pub rules: Vec<Rule>,
+    pub quotas: Vec<Quota>,
+    /// The block page theme, from `page::THEMES`.
+    pub theme: Option<String>,
+    pub safe_search: Option<bool>,
+}
+
+/// Search and video hosts and their providers' safe-search names, unless the rule file says otherwise.
+pub const SAFE_SEARCH: [(&str, &str); 10] = [
+    ("google.com", "forcesafesearch.google.com"),
+    ("www.google.com", "forcesafesearch.google.com"),
+    ("www.bing.com", "strict.bing.com"),
+    ("duckduckgo.com", "safe.duckduckgo.com"),
+    ("www.duckduckgo.com", "safe.duckduckgo.com"),
+    ("www.youtube.com", "restrict.youtube.com"),
+    ("m.youtube.com", "restrict.youtube.com"),
+    ("youtubei.googleapis.com", "restrict.youtube.com"),
+    ("youtube.googleapis.com", "restrict.youtube.com"),
+    ("www.youtube-nocookie.com", "restrict.youtube.com"),
+];
+
+/// An address, or a network in CIDR notation, that a client can belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientMatch {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl ClientMatch {
+    fn parse(word: &str) -> Result<ClientMatch, String> {
+        let bad = || format!("bad client address {}", word);
+        let (addr, prefix) = word.split_once('/').unwrap_or((word, ""));
+        let addr: IpAddr = addr.parse().map_err(|_| bad())?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = if prefix.is_empty() { bits } else { prefix.parse().ok().filter(|p| *p <= bits).ok_or_else(bad)? };
+        Ok(ClientMatch { addr, prefix })
+    }
+
+    pub fn contains(&self, client: IpAddr) -> bool {
+        let (net, client, bits) = match (self.addr, client.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(client)) => (u32::from(net) as u128, u32::from(client) as u128, 32),
+            (IpAddr::V6(net), IpAddr::V6(client)) => (u128::from(net), u128::from(client), 128),
+            _ => return false,
+        };
+        let shift = bits - self.prefix as u32;
+        shift == bits || net >> shift == client >> shift
+    }
+}
+
+/// Addresses, networks and named devices, as listed for a group.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Members {
+    pub clients: Vec<ClientMatch>,
+    /// Names of `device`s.
+    pub devices: Vec<String>,
+}
+
+impl Members {
+    /// Reads a list of members, noting the device names so they can be checked once the whole file's read.
+    fn parse(words: &[&str], line: usize, named: &mut Vec<(usize, String)>) -> Result<Members, String> {
+        let mut members = Members::default();
+        for word in words {
+            // Anything that looks like an address has to be one; other words are device names.
+            if word.contains(['.', ':', '/']) {
+                members.clients.push(ClientMatch::parse(word)?);
+            } else {
+                named.push((line, word.to_string()));
+                members.devices.push(word.to_string());
+            }
+        }
+        Ok(members)
+    }
+
+    pub fn contains(&self, client: &Client) -> bool {
+        self.clients.iter().any(|c| c.contains(client.ip)) || client.device.as_ref().is_some_and(|d| self.devices.contains(d))
+    }
+}
+
+/// Clients that get their own rules ahead of the global ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    pub members: Members,
+    pub rules: RuleSet,
+}
+
+/// The outcome for one request and the rule responsible.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decision {
+    pub action: Action,
+    /// Which rule matched, e.g. `line 12: deny games (*.roblox.com)` or `default allow`.
+    pub reason: String,
+}
+
+impl Decision {
+    pub fn allowed(&self) -> bool {
+        self.action == Action::Allow
+    }
+}
+
+/** A parsed rule file.
+
+```text
+# Anything not matched below is allowed.
+default allow
+category games roblox.com *.roblox.com *.minecraft.net
+category video youtube.com *.youtube.com *.googlevideo.com
+deny games
+
+# Times are in this timezone, or the machine's own without one.
+timezone America/Chicago
+schedule homework mon-thu 15:30-18:00
+schedule homework sun 18:00-20:00
+schedule bedtime daily 20:30-07:00
+
+# Devices go by addresses or MAC addresses.
+device tablet 192.168.1.20 aa:bb:cc:dd:ee:01
+
+# Rules for the kids' devices come first for them, then the ones above.
+group kids tablet 192.168.1.21 10.0.5.0/24
+deny * during bedtime
+allow *.khanacademy.org
+deny video during homework
+quota video 1h
+default deny
+```
+
+Hosts are exact names, `*.domain` for every name under `domain`, or `*` for all of them.
+`allow` and `deny` take any mix of host patterns and category names, and `during NAME`
+at the end limits them to a schedule. Rules are checked in file order and the first one
+that matches and is in effect decides; a client in a group has its group's rules checked
+before the global ones. Categories and schedules can be defined, or extended by repeating
+them, anywhere in the file. A schedule's days are as in `Window::parse`.
+
+A group lists addresses, networks and device names. A `quota` gives each device in it
+that much time a day on a category (like `45m` or `1h30m`; see `quota::Usage` for how
+it's counted), once the rules have allowed it. Without a group quota, a global one for
+the same hosts applies.
+
+`theme NAME` picks the block page for everyone or for a group; see `page::THEMES`.
+`admin` lists who besides the proxy's own machine may use the admin page, like a group.
+
+In DNS mode, search engines and video sites are answered with their safe-search names
+(see `SAFE_SEARCH`) for clients in a group. `safesearch on` or `safesearch off` changes
+that for everyone or for a group, and `safesearch HOST TARGET` sends another host to a
+safe-search name, or with `off` as the target leaves it alone.
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rules {
+    pub categories: HashMap<String, Vec<Pattern>>,
+    pub devices: Vec<Device>,
+    /// Who may approve requests for access, besides the proxy's own machine.
+    pub admins: Members,
+    pub schedules: HashMap<String, Schedule>,
+    /// The timezone schedules are in; the system's local time if not set.
+    pub timezone: Option<Tz>,
+    /// Safe-search names that replace, or with `None` remove, those in `SAFE_SEARCH`.
+    pub safe_search: HashMap<String, Option<String>>,
+    pub global: RuleSet,
+    pub groups: Vec<Group>,
+}
+
+impl Rules {
+    /// Allows everything, for running without a rule file.
+    pub fn allow_all() -> Rules {
+        Rules { global: RuleSet { default: Some(Action::Allow), ..RuleSet::default() }, ..Rules::default() }
+    }
+
+    pub fn load(path: &str) -> Result<Rules, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+        Rules::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Rules, String> {
+        let mut rules = Rules::default();
+        let mut used = vec![];
+        let mut scheduled = vec![];
+        let mut named = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let number = i + 1;
+            let at = |e: String| format!("line {}: {}", number, e);
+            let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+            let Some(keyword) = words.next() else {
+                continue;
+            };
+            let words: Vec<&str> = words.collect();
+            let set = match rules.groups.last_mut() {
+                Some(group) => &mut group.rules,
+                None => &mut rules.global,
+            };
+            match (keyword, words.as_slice()) {
+                ("default", [action]) => set.default = Some(Action::parse(action).ok_or_else(|| at(format!("default must be allow or deny, not {}", action)))?),
+                ("category", [name, hosts @ ..]) if !hosts.is_empty() => {
+                    let patterns = hosts.iter().map(|h| Pattern::parse(h)).collect::<Result<Vec<_>, _>>().map_err(at)?;
+                    rules.categories.entry(name.to_string()).or_default().extend(patterns);
+                }
+                ("timezone", [name]) => rules.timezone = Some(name.parse().map_err(|_| at(format!("unknown timezone {}", name)))?),
+                ("schedule", [name, days, times @ ..]) if times.len() <= 1 => {
+                    let window = Window::parse(days, times.first().copied()).map_err(at)?;
+                    rules.schedules.entry(name.to_string()).or_default().windows.push(window);
+                }
+                ("device", [name, addresses @ ..]) if !addresses.is_empty() => {
+                    let mut device = Device { name: name.to_string(), ips: vec![], macs: vec![] };
+                    for address in addresses {
+                        match (address.parse(), devices::parse_mac(address)) {
+                            (Ok(ip), _) => device.ips.push(ip),
+                            (_, Some(mac)) => device.macs.push(mac),
+                            _ => return Err(at(format!("bad device address {}", address))),
+                        }
+                    }
+                    rules.devices.push(device);
+                }
+                ("group", [name, clients @ ..]) if !clients.is_empty() => {
+                    let members = Members::parse(clients, number, &mut named).map_err(at)?;
+                    rules.groups.push(Group { name: name.to_string(), members, rules: RuleSet::default() });
+                }
+                ("admin", clients) if !clients.is_empty() => {
+                    let admins = Members::parse(clients, number, &mut named).map_err(at)?;
+                    rules.admins.clients.extend(admins.clients);
+                    rules.admins.devices.extend(admins.devices);
+                }
+                ("theme", [name]) => match page::theme(name) {
+                    Some(_) => set.theme = Some(name.to_string()),
+                    None => return Err(at(format!("no theme called {}", name))),
+                },
+                ("safesearch", [on @ ("on" | "off")]) => set.safe_search = Some(*on == "on"),
+                ("safesearch", [host, target]) => {
+                    let target = Some(target.to_ascii_lowercase()).filter(|t| t != "off");
+                    rules.safe_search.insert(host.to_ascii_lowercase(), target);
+                }
+                ("quota", [category, time]) => {
+                    let minutes = parse_duration(time).ok_or_else(|| at(format!("bad duration {}", time)))?;
+                    used.push((number, category.to_string()));
+                    set.quotas.push(Quota { line: number, category: category.to_string(), minutes });
+                }
+                ("allow" | "deny", [targets @ .., "during", schedule]) if !targets.is_empty() => {
+                    scheduled.push((number, schedule.to_string()));
+                    push_rules(set, &mut used, number, keyword, targets, Some(schedule)).map_err(at)?;
+                }
+                ("allow" | "deny", targets) if !targets.is_empty() && !targets.contains(&"during") => {
+                    push_rules(set, &mut used, number, keyword, targets, None).map_err(at)?;
+                }
+                _ => return Err(at(format!("can't read {:?}", line.trim()))),
+            }
+        }
+        // Categories can be defined after they're used, so check names once everything's read.
+        if let Some((number, name)) = used.iter().find(|(_, name)| !rules.categories.contains_key(name)) {
+            return Err(format!("line {}: no category called {}", number, name));
+        }
+        if let Some((number, name)) = scheduled.iter().find(|(_, name)| !rules.schedules.contains_key(name)) {
+            return Err(format!("line {}: no schedule called {}", number, name));
+        }
+        if let Some((number, name)) = named.iter().find(|(_, name)| !rules.devices.iter().any(|d| d.name == *name)) {
+            return Err(format!("line {}: no device called {}", number, name));
+        }
+        Ok(rules)
+    }
+
+    /// `now` on the clock the schedules are written for.
+    pub fn local(&self, now: DateTime<Utc>) -> NaiveDateTime {
+        match self.timezone {
+            Some(tz) => now.with_timezone(&tz).naive_local(),
+            None => now.with_timezone(&Local).naive_local(),
+        }
+    }
+
+    /// Whether any device has to be recognized by its MAC address.
+    pub fn uses_macs(&self) -> bool {
+        self.devices.iter().any(|d| !d.macs.is_empty())
+    }
+
+    /// Who a request from `ip` is from, given the MAC address seen for it.
+    pub fn client(&self, ip: IpAddr, mac: Option<&str>) -> Client {
+        let mut client = Client::new(ip);
+        let device = self.devices.iter().find(|d| d.ips.iter().any(|i| i.to_canonical() == client.ip) || mac.is_some_and(|m| d.macs.iter().any(|d| d == m)));
+        client.device = device.map(|d| d.name.clone());
+        client
+    }
+
+    /// The group `client` belongs to, if any; the first listed wins.
+    pub fn group(&self, client: &Client) -> Option<&Group> {
+        self.groups.iter().find(|g| g.members.contains(client))
+    }
+
+    /// Whether `client` may use the admin page: the proxy's own machine, or anyone on an `admin` line.
+    pub fn is_admin(&self, client: &Client) -> bool {
+        client.ip.is_loopback() || self.admins.contains(client)
+    }
+
+    /// The rules that apply to `client`: their group's, then everyone's.
+    fn sets<'a>(&'a self, client: &Client) -> impl Iterator<Item = &'a RuleSet> + Clone {
+        self.group(client).map(|g| &g.rules).into_iter().chain([&self.global])
+    }
+
+    /// The block page theme for `client`.
+    pub fn theme(&self, client: &Client) -> &str {
+        self.sets(client).find_map(|set| set.theme.as_deref()).unwrap_or(page::DEFAULT_THEME)
+    }
+
+    /// The safe-search name to answer for `host` with, if `client` gets safe search.
+    pub fn safe_search(&self, client: &Client, host: &str) -> Option<&str> {
+        if !self.sets(client).find_map(|set| set.safe_search).unwrap_or(self.group(client).is_some()) {
+            return None;
+        }
+        match self.safe_search.get(host) {
+            Some(target) => target.as_deref(),
+            None => SAFE_SEARCH.iter().find(|(name, _)| *name == host).map(|(_, target)| *target),
+        }
+    }
+
+    /** The first minute after `now`, within a week, when the rules let `client` visit `host`.
+
+    With `skip_today`, for when a used-up quota is what's in the way, it's the first such
+    minute from tomorrow on.
+    */
+    pub fn next_allowed(&self, client: &Client, host: &str, now: DateTime<Utc>, skip_today: bool) -> Option<DateTime<Utc>> {
+        let today = self.local(now).date();
+        let start = now.duration_trunc(Duration::minutes(1)).ok()?;
+        (1..=7 * 24 * 60)
+            .map(|m| start + Duration::minutes(m))
+            .filter(|t| !skip_today || self.local(*t).date() != today)
+            .find(|t| self.check(client, host, *t).allowed())
+    }
+
+    /// The quota `host` counts against for `client`, if any.
+    pub fn quota(&self, client: &Client, host: &str) -> Option<&Quota> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.sets(client).flat_map(|set| &set.quotas).find(|q| self.categories[&q.category].iter().any(|p| p.matches(&host)))
+    }
+
+    /// Whether `client` may visit `host` at `now`, and why. Quotas are left to the caller.
+    pub fn check(&self, client: &Client, host: &str, now: DateTime<Utc>) -> Decision {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let local = self.local(now);
+        let group = self.group(client);
+        let sets = self.sets(client);
+        for rule in sets.clone().flat_map(|set| &set.rules) {
+            if rule.during.as_ref().is_some_and(|name| !self.schedules[name].contains(&local)) {
+                continue;
+            }
+            if let Some(pattern) = self.matching(&rule.target, &host) {
+                let mut reason = match &rule.target {
+                    Target::Host(_) => format!("line {}: {} {}", rule.line, rule.action, pattern),
+                    Target::Category(name) => format!("line {}: {} {} ({})", rule.line, rule.action, name, pattern),
+                };
+                if let Some(name) = &rule.during {
+                    reason.push_str(&format!(" during {}", name));
+                }
+                return Decision { action: rule.action, reason };
+            }
+        }
+        let action = sets.filter_map(|set| set.default).next().unwrap_or(Action::Allow);
+        let reason = match group.filter(|g| g.rules.default.is_some()) {
+            Some(group) => format!("default {} for {}", action, group.name),
+            None => format!("default {}", action),
+        };
+        Decision { action, reason }
+    }
+
+    /// The pattern in `target` that matches `host`.
+    fn matching<'a>(&'a self, target: &'a Target, host: &str) -> Option<&'a Pattern> {
+        match target {
+            Target::Host(pattern) => Some(pattern).filter(|p| p.matches(host)),
+            Target::Category(name) => self.categories.get(name)?.iter().find(|p| p.matches(host)),
+        }
+    }
+}
+
+/// Minutes in `1h`, `45m` or `1h30m`.
+fn parse_duration(text: &str) -> Option<u32> {
+    let (hours, rest) = match text.split_once('h') {
+        Some((hours, rest)) => (hours.parse().ok()?, rest),
+        None => (0, text),
+    };
+    let minutes = match rest {
+        "" => 0,
+        _ => rest.strip_suffix('m')?.parse().ok()?,
+    };
+    (text != "h" && hours * 60 + minutes > 0).then_some(hours * 60 + minutes)
+}
+
+/// `90` minutes as `1h30m`.
+pub fn duration(minutes: u32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{}m", h, m),
+    }
+}
+
+/// Adds a rule to `set` for each of `targets`, noting the categories it uses.
+fn push_rules(set: &mut RuleSet, used: &mut Vec<(usize, String)>, line: usize, action: &str, targets: &[&str], during: Option<&str>) -> Result<(), String> {
+    let action = Action::parse(action).unwrap();
+    for word in targets {
+        let target = if word.contains('.') || *word == "*" { Target::Host(Pattern::parse(word)?) } else { Target::Category(word.to_string()) };
+        if let Target::Category(name) = &target {
+            used.push((line, name.clone()));
+        }
+        set.rules.push(Rule { line, action, target, during: during.map(|d| d.to_string()) });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = "
+# Everyone
+default allow
+category games roblox.com *.roblox.com
+deny games
+deny ads.example.com
+
+group kids 192.168.1.20 10.0.5.0/24
+allow *.khanacademy.org
+deny video   # defined below
+default deny
+
+category video *.youtube.com
+";
+
+    fn client(ip: &str) -> Client {
+        Client::new(ip.parse().unwrap())
+    }
+
+    /// `time` as UTC, e.g. `2024-06-03T12:00:00Z`, a Monday.
+    fn utc(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_check() {
+        let rules = Rules::parse(RULES).unwrap();
+        let check = |ip: &str, host: &str| {
+            let d = rules.check(&client(ip), host, utc("2024-06-03T12:00:00Z"));
+            (d.allowed(), d.reason)
+        };
+        let parent = "192.168.1.2";
+        assert_eq!(check(parent, "www.roblox.com"), (false, String::from("line 5: deny games (*.roblox.com)")));
+        assert_eq!(check(parent, "Ads.Example.com."), (false, String::from("line 6: deny ads.example.com")));
+        assert_eq!(check(parent, "www.youtube.com"), (true, String::from("default allow")));
+        // Wildcards don't cover the domain itself and exact names don't cover subdomains.
+        assert!(check(parent, "x.ads.example.com").0);
+        assert_eq!(check("10.0.5.7", "khanacademy.org"), (false, String::from("default deny for kids")));
+        assert_eq!(check("10.0.5.7", "www.khanacademy.org"), (true, String::from("line 9: allow *.khanacademy.org")));
+        assert_eq!(check("192.168.1.20", "m.youtube.com"), (false, String::from("line 10: deny video (*.youtube.com)")));
+        // Group members still get the global rules after their own.
+        assert_eq!(check("::ffff:192.168.1.20", "roblox.com"), (false, String::from("line 5: deny games (roblox.com)")));
+    }
+
+    #[test]
+    fn test_example() {
+        let rules = Rules::parse(include_str!("../rules.txt")).unwrap();
+        let monday_noon = utc("2024-06-03T17:00:00Z");
+        let tablet = rules.client("192.168.1.20".parse().unwrap(), None);
+        assert!(!rules.check(&tablet, "www.roblox.com", monday_noon).allowed());
+        assert!(rules.check(&tablet, "www.roblox.com", utc("2024-06-08T17:00:00Z")).allowed());
+        assert!(rules.check(&client("192.168.1.2"), "www.roblox.com", monday_noon).allowed());
+        assert_eq!(rules.quota(&tablet, "www.youtube.com").unwrap().minutes, 60);
+        assert!(rules.is_admin(&rules.client("192.168.1.30".parse().unwrap(), None)) && !rules.is_admin(&tablet));
+    }
+
+    #[test]
+    fn test_schedules() {
+        let rules = Rules::parse(
+            "timezone America/New_York
+schedule homework mon-thu 15:30-18:00
+schedule bedtime daily 20:30-07:00
+schedule weekend weekends
+category video *.youtube.com
+group kids 10.0.0.2
+deny * during bedtime
+deny video during homework
+allow video during weekend
+deny video",
+        )
+        .unwrap();
+        let check = |host: &str, time: &str| rules.check(&client("10.0.0.2"), host, utc(time)).reason;
+        // New York is UTC-4 in June: 20:00 UTC is 16:00 there.
+        assert_eq!(check("www.youtube.com", "2024-06-03T20:00:00Z"), "line 8: deny video (*.youtube.com) during homework");
+        assert_eq!(check("www.youtube.com", "2024-06-03T22:30:00Z"), "line 10: deny video (*.youtube.com)");
+        assert_eq!(check("example.com", "2024-06-04T01:00:00Z"), "line 7: deny * during bedtime");
+        assert_eq!(check("example.com", "2024-06-04T11:00:00Z"), "default allow");
+        // Saturday afternoon.
+        assert_eq!(check("www.youtube.com", "2024-06-08T18:00:00Z"), "line 9: allow video (*.youtube.com) during weekend");
+        // Winter is UTC-5, so 20:00 UTC is 15:00, before homework.
+        assert_eq!(check("www.youtube.com", "2024-01-08T20:00:00Z"), "line 10: deny video (*.youtube.com)");
+    }
+
+    #[test]
+    fn test_devices_and_quotas() {
+        let rules = Rules::parse(
+            "category video *.youtube.com
+category games *.roblox.com
+device tablet 192.168.1.20 AA:BB:CC:DD:EE:01
+group kids tablet
+quota video 1h30m
+quota games 20m
+group others 10.0.0.0/8
+quota games 45m",
+        )
+        .unwrap();
+        assert!(rules.uses_macs());
+        let tablet = rules.client("192.168.1.20".parse().unwrap(), None);
+        assert_eq!(tablet.name(), "tablet");
+        // Found by MAC address on a new IP.
+        let moved = rules.client("192.168.1.99".parse().unwrap(), Some("aa:bb:cc:dd:ee:01"));
+        assert_eq!((moved.name(), rules.group(&moved).unwrap().name.as_str()), (String::from("tablet"), "kids"));
+        assert_eq!(rules.quota(&moved, "m.youtube.com").unwrap().to_string(), "line 5: quota video 1h30m");
+        assert_eq!(rules.quota(&tablet, "www.roblox.com").unwrap().minutes, 20);
+        assert_eq!(rules.quota(&client("10.1.2.3"), "www.roblox.com").unwrap().minutes, 45);
+        assert_eq!(client("10.1.2.3").name(), "10.1.2.3");
+        assert_eq!(rules.quota(&client("192.168.1.2"), "www.roblox.com"), None);
+        assert_eq!(rules.quota(&tablet, "example.com"), None);
+        assert!(!rules.is_admin(&tablet) && rules.is_admin(&client("127.0.0.1")));
+        let rules = Rules::parse("device phone 192.168.1.5\nadmin phone 192.168.1.6").unwrap();
+        assert!(rules.is_admin(&rules.client("192.168.1.5".parse().unwrap(), None)) && rules.is_admin(&client("192.168.1.6")));
+    }
+
+    #[test]
+    fn test_safe_search() {
+        let rules = Rules::parse(
+            "safesearch www.youtube.com off
+safesearch search.example.com Safe.Example.com
+group kids 10.0.0.2
+group teens 10.0.0.3
+safesearch off",
+        )
+        .unwrap();
+        let (kid, teen) = (client("10.0.0.2"), client("10.0.0.3"));
+        assert_eq!(rules.safe_search(&kid, "www.google.com"), Some("forcesafesearch.google.com"));
+        assert_eq!(rules.safe_search(&kid, "search.example.com"), Some("safe.example.com"));
+        assert_eq!(rules.safe_search(&kid, "www.youtube.com"), None);
+        assert_eq!(rules.safe_search(&kid, "example.com"), None);
+        assert_eq!(rules.safe_search(&teen, "www.google.com"), None);
+        assert_eq!(rules.safe_search(&client("10.0.0.4"), "www.google.com"), None);
+        let everyone = Rules::parse("safesearch on").unwrap();
+        assert_eq!(everyone.safe_search(&client("10.0.0.4"), "m.youtube.com"), Some("restrict.youtube.com"));
+    }
+
+    #[test]
+    fn test_next_allowed() {
+        let rules = Rules::parse("timezone Europe/Paris\nschedule bedtime daily 21:00-07:00\ndeny * during bedtime\ntheme night").unwrap();
+        let kid = client("10.0.0.2");
+        // 22:30 in Paris.
+        let night = utc("2024-06-03T20:30:15Z");
+        assert_eq!(rules.next_allowed(&kid, "example.com", night, false), Some(utc("2024-06-04T05:00:00Z")));
+        assert_eq!(rules.next_allowed(&kid, "example.com", utc("2024-06-04T09:00:00Z"), false), Some(utc("2024-06-04T09:01:00Z")));
+        // The first allowed minute after midnight is still bedtime, so it's the morning.
+        assert_eq!(rules.next_allowed(&kid, "example.com", utc("2024-06-03T12:00:00Z"), true), Some(utc("2024-06-04T05:00:00Z")));
+        assert_eq!(Rules::parse("deny *").unwrap().next_allowed(&kid, "example.com", night, false), None);
+        assert_eq!(rules.theme(&kid), "night");
+        assert_eq!(Rules::allow_all().theme(&kid), page::DEFAULT_THEME);
+        assert_eq!(rules.safe_search(&kid, "www.google.com"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Rules::parse("default maybe").unwrap_err(), "line 1: default must be allow or deny, not maybe");
+        assert_eq!(Rules::parse("\ndeny games").unwrap_err(), "line 2: no category called games");
+        assert_eq!(Rules::parse("group kids 192.168.1.300").unwrap_err(), "line 1: bad client address 192.168.1.300");
+        assert_eq!(Rules::parse("allow a.*.com").unwrap_err(), "line 1: bad host pattern a.*.com");
+        assert_eq!(Rules::parse("block example.com").unwrap_err(), "line 1: can't read \"block example.com\"");
+        assert_eq!(Rules::parse("timezone Mars/Olympus").unwrap_err(), "line 1: unknown timezone Mars/Olympus");
+        assert_eq!(Rules::parse("deny * during recess").unwrap_err(), "line 1: no schedule called recess");
+        assert_eq!(Rules::parse("schedule naps daily 13:00").unwrap_err(), "line 1: bad time range 13:00");
+        assert_eq!(Rules::parse("group kids tablet").unwrap_err(), "line 1: no device called tablet");
+        assert_eq!(Rules::parse("device tablet 192.168.1").unwrap_err(), "line 1: bad device address 192.168.1");
+        assert_eq!(Rules::parse("category video v.com\nquota video 1h60").unwrap_err(), "line 2: bad duration 1h60");
+        assert_eq!(duration(90), "1h30m");
+        assert_eq!(Rules::parse("theme disco").unwrap_err(), "line 1: no theme called disco");
+        let everyone = Rules::parse("group all 0.0.0.0/0\ndefault deny").unwrap();
+        assert_eq!(everyone.check(&client("8.8.8.8"), "a.b", Utc::now()).reason, "default deny for all");
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Datelike, Timelike, Utc, Weekday};
+
+/// Minutes in a day; a window may end at `24:00`.
+const DAY: u32 = 24 * 60;
+
+/// Where the proxy gets the time from, so tests can pick it.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// The same time until it's moved on by hand.
+pub struct FixedClock(pub Mutex<DateTime<Utc>>);
+
+impl FixedClock {
+    pub fn new(time: DateTime<Utc>) -> FixedClock {
+        FixedClock(Mutex::new(time))
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// A shared clock, so a test can keep hold of the one it gave away.
+impl<C: Clock> Clock for Arc<C> {
+    fn now(&self) -> DateTime<Utc> {
+        (**self).now()
+    }
+}
+
+/** Part of a week: some days, and a time range on each of them in minutes from midnight.
+
+A range that ends before it starts runs past midnight, so `fri 20:30-07:00` covers
+Friday night until 7 on Saturday morning.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Window {
+    /// Indexed from Monday.
+    pub days: [bool; 7],
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Window {
+    /** Reads `DAYS [HH:MM-HH:MM]`, where days are a comma-separated list of names
+    (`mon`, `tue`, ...), ranges (`mon-fri`, or `sun-thu` going round the weekend) or
+    `daily`, `weekdays` and `weekends`. Without a time range the window is the whole of
+    each day.
+    */
+    pub fn parse(days: &str, times: Option<&str>) -> Result<Window, String> {
+        let mut window = Window { days: [false; 7], start: 0, end: DAY };
+        for part in days.split(',') {
+            let (first, last) = match part {
+                "daily" => (0, 6),
+                "weekdays" => (0, 4),
+                "weekends" => (5, 6),
+                _ => match part.split_once('-') {
+                    Some((first, last)) => (day(first)?, day(last)?),
+                    None => (day(part)?, day(part)?),
+                },
+            };
+            let mut d = first;
+            window.days[d] = true;
+            while d != last {
+                d = (d + 1) % 7;
+                window.days[d] = true;
+            }
+        }
+        if let Some(times) = times {
+            let (start, end) = times.split_once('-').ok_or_else(|| format!("bad time range {}", times))?;
+            (window.start, window.end) = (minutes(start)?, minutes(end)?);
+            if window.start == window.end {
+                return Err(format!("empty time range {}", times));
+            }
+        }
+        Ok(window)
+    }
+
+    /// Whether the window covers `minute` on day `weekday` (0 for Monday).
+    fn contains(&self, weekday: usize, minute: u32) -> bool {
+        if self.start < self.end {
+            self.days[weekday] && self.start <= minute && minute < self.end
+        } else {
+            let yesterday = (weekday + 6) % 7;
+            (self.days[weekday] && minute >= self.start) || (self.days[yesterday] && minute < self.end)
+        }
+    }
+}
+
+/// Index from Monday of a three-letter day name.
+fn day(name: &str) -> Result<usize, String> {
+    [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun]
+        .iter()
+        .position(|d| d.to_string().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("bad day {}", name))
+}
+
+/// Minutes from midnight of `HH:MM`, up to `24:00`.
+fn minutes(time: &str) -> Result<u32, String> {
+    let bad = || format!("bad time {}", time);
+    let (h, m) = time.split_once(':').ok_or_else(bad)?;
+    let (h, m): (u32, u32) = (h.parse().map_err(|_| bad())?, m.parse().map_err(|_| bad())?);
+    if m >= 60 || h * 60 + m > DAY {
+        return Err(bad());
+    }
+    Ok(h * 60 + m)
+}
+
+/// A named set of windows, like `homework` or `bedtime`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schedule {
+    pub windows: Vec<Window>,
+}
+
+impl Schedule {
+    /// Whether `local`, a time in the rules' timezone, is in any of the windows.
+    pub fn contains<T: Datelike + Timelike>(&self, local: &T) -> bool {
+        let weekday = local.weekday().num_days_from_monday() as usize;
+        let minute = local.hour() * 60 + local.minute();
+        self.windows.iter().any(|w| w.contains(weekday, minute))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// A time on 2024-06-03, a Monday, plus `day` days.
+    fn at(day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, 3 + day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_window() {
+        let homework = Schedule { windows: vec![Window::parse("mon-thu,sun", Some("15:30-18:00")).unwrap()] };
+        assert!(homework.contains(&at(0, 15, 30)));
+        assert!(!homework.contains(&at(0, 18, 0)));
+        assert!(!homework.contains(&at(4, 16, 0)));
+        assert!(homework.contains(&at(6, 16, 0)));
+        let bedtime = Schedule { windows: vec![Window::parse("fri", Some("20:30-07:00")).unwrap()] };
+        assert!(!bedtime.contains(&at(4, 6, 0)));
+        assert!(bedtime.contains(&at(4, 23, 59)));
+        assert!(bedtime.contains(&at(5, 6, 59)));
+        assert!(!bedtime.contains(&at(5, 20, 30)));
+        let weekend = Schedule { windows: vec![Window::parse("weekends", None).unwrap()] };
+        assert!(weekend.contains(&at(5, 0, 0)) && weekend.contains(&at(6, 23, 59)) && !weekend.contains(&at(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Window::parse("fri-mon", None).unwrap().days, [true, false, false, false, true, true, true]);
+        assert_eq!(Window::parse("someday", None).unwrap_err(), "bad day someday");
+        assert_eq!(Window::parse("daily", Some("7:00")).unwrap_err(), "bad time range 7:00");
+        assert_eq!(Window::parse("daily", Some("7:00-24:01")).unwrap_err(), "bad time 24:01");
+        assert_eq!(Window::parse("daily", Some("7:00-7:00")).unwrap_err(), "empty time range 7:00-7:00");
+        assert_eq!(Window::parse("daily", Some("0:00-24:00")).unwrap().end, DAY);
+    }
+}
@@ -0,0 +1,210 @@
+use std::io::{self, Read};
+
+/// TLS record type for handshake messages.
+const HANDSHAKE: u8 = 22;
+/// Handshake message type of a ClientHello.
+const CLIENT_HELLO: u8 = 1;
+/// Extension carrying the server name.
+const SERVER_NAME: u16 = 0;
+/// Largest ClientHello we'll buffer; real ones are a few KB at most.
+const MAX_HELLO: usize = 64 * 1024;
+
+/** A fatal `access_denied` alert, sent instead of passing a blocked ClientHello on.
+
+Browsers show this as a connection error rather than a certificate warning, since no
+certificate was ever offered.
+*/
+pub const ACCESS_DENIED: [u8; 7] = [21, 3, 1, 0, 2, 2, 49];
+
+/** Reads the records holding a TLS ClientHello from the start of a tunnel.
+
+Returns every byte read, so it can be passed on unchanged, and the server name it asks
+for. If the client isn't speaking TLS the bytes come back with no name, after reading as
+little as possible, and so does nothing at all if the reader ends or times out first.
+Once the first byte says it's a TLS handshake, though, anything short of a whole
+ClientHello (cut off, timed out, too big or some other message) is an `InvalidData`
+error, so a hello can't get past unread by arriving slowly.
+*/
+pub fn read_client_hello(reader: &mut impl Read) -> io::Result<(Vec<u8>, Option<String>)> {
+    let unfinished = || io::Error::new(io::ErrorKind::InvalidData, "TLS handshake without a whole ClientHello");
+    let mut raw = vec![];
+    let mut handshake = vec![];
+    loop {
+        let mut header = [0; 5];
+        let n = read_some(reader, &mut header)?;
+        raw.extend_from_slice(&header[..n]);
+        if raw.first().is_none_or(|b| *b != HANDSHAKE) {
+            return Ok((raw, None));
+        }
+        if n < header.len() || header[0] != HANDSHAKE {
+            return Err(unfinished());
+        }
+        let length = u16::from_be_bytes([header[3], header[4]]) as usize;
+        let start = raw.len();
+        raw.resize(start + length, 0);
+        let n = read_some(reader, &mut raw[start..])?;
+        raw.truncate(start + n);
+        handshake.extend_from_slice(&raw[start..]);
+        if n < length || raw.len() > MAX_HELLO {
+            return Err(unfinished());
+        }
+        if handshake.len() >= 4 {
+            if handshake[0] != CLIENT_HELLO {
+                return Err(unfinished());
+            }
+            let hello_length = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+            if handshake.len() >= 4 + hello_length {
+                return Ok((raw, server_name(&handshake[4..4 + hello_length])));
+            }
+        }
+    }
+}
+
+/// Fills as much of `buf` as the reader gives before it ends, returning how much that was.
+fn read_some(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            // A read timeout, which ends the reading but not what was read.
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// The `host_name` in a ClientHello body's server name extension, lowercased.
+pub fn server_name(hello: &[u8]) -> Option<String> {
+    let mut body = Cursor(hello);
+    // Legacy version and random.
+    body.take(2 + 32)?;
+    let session = body.u8()? as usize;
+    body.take(session)?;
+    let suites = body.u16()? as usize;
+    body.take(suites)?;
+    let compression = body.u8()? as usize;
+    body.take(compression)?;
+    let length = body.u16()? as usize;
+    let mut extensions = Cursor(body.take(length)?);
+    while !extensions.0.is_empty() {
+        let kind = extensions.u16()?;
+        let length = extensions.u16()? as usize;
+        let data = extensions.take(length)?;
+        if kind != SERVER_NAME {
+            continue;
+        }
+        let mut list = Cursor(data);
+        let length = list.u16()? as usize;
+        let mut names = Cursor(list.take(length)?);
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let length = names.u16()? as usize;
+            let name = names.take(length)?;
+            if name_type == 0 {
+                return String::from_utf8(name.to_vec()).ok().map(|n| n.to_ascii_lowercase());
+            }
+        }
+    }
+    None
+}
+
+/// Reads big-endian fields off the front of a slice.
+pub struct Cursor<'a>(pub &'a [u8]);
+
+impl<'a> Cursor<'a> {
+    pub fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.0.len() {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        let b = self.take(2)?;
+        Some(u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+/// A minimal ClientHello record asking for `name`, split over `records` records.
+#[cfg(test)]
+pub fn client_hello(name: &str, records: usize) -> Vec<u8> {
+    let name = name.as_bytes();
+    let mut sni = vec![];
+    sni.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+    sni.push(0);
+    sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    sni.extend_from_slice(name);
+    let mut extensions = vec![0, 10, 0, 2, 0, 29]; // supported_groups, before the name
+    extensions.extend_from_slice(&SERVER_NAME.to_be_bytes());
+    extensions.extend_from_slice(&(sni.len() as u16).to_be_bytes());
+    extensions.extend_from_slice(&sni);
+    let mut hello = vec![3, 3];
+    hello.extend_from_slice(&[7; 32]);
+    hello.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0]);
+    hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&extensions);
+    let mut handshake = vec![CLIENT_HELLO];
+    handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&hello);
+    let mut out = vec![];
+    for chunk in handshake.chunks(handshake.len().div_ceil(records)) {
+        out.extend_from_slice(&[HANDSHAKE, 3, 1]);
+        out.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_client_hello() {
+        let hello = client_hello("Games.Example.com", 1);
+        let mut input = [hello.as_slice(), b"after"].concat();
+        let (raw, name) = read_client_hello(&mut input.as_slice()).unwrap();
+        assert_eq!(raw, hello);
+        assert_eq!(name.as_deref(), Some("games.example.com"));
+        // A hello split over several records.
+        let split = client_hello("example.com", 3);
+        let (raw, name) = read_client_hello(&mut split.as_slice()).unwrap();
+        assert_eq!((raw, name.as_deref()), (split.clone(), Some("example.com")));
+        // Not TLS: only the first few bytes are taken.
+        input = b"SSH-2.0-OpenSSH\r\n".to_vec();
+        assert_eq!(read_client_hello(&mut input.as_slice()).unwrap(), (b"SSH-2".to_vec(), None));
+        // Cut short, or timed out partway, is refused rather than let through unread.
+        for end in 1..hello.len() {
+            assert_eq!(read_client_hello(&mut &hello[..end]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        assert_eq!(read_client_hello(&mut (&hello[..40]).chain(Stalled)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // Nothing at all yet, as when the server speaks first.
+        assert_eq!(read_client_hello(&mut Stalled).unwrap(), (vec![], None));
+    }
+
+    /// A reader whose read timeout has passed.
+    struct Stalled;
+
+    impl Read for Stalled {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    #[test]
+    fn test_server_name_malformed() {
+        let hello = client_hello("example.com", 1);
+        for end in 9..hello.len() {
+            assert_eq!(server_name(&hello[9..end]), None);
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use grid::Grid;
+use rand::Rng;
+
+use crate::{division, multiplication};
+
+/// Operands are cut down to at most six digits, like `prepareOperand` in `worksheets/grid.html`.
+const MAX_VAL: i64 = 999_999;
+/// Random draws allowed per problem before deciding the regrouping rules can't be met.
+pub const MAX_TRIES: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Operator {
+    pub fn symbol(&self) -> char {
+        match self {
+            Operator::Add => '+',
+            Operator::Subtract => '-',
+            Operator::Multiply => '×',
+            Operator::Divide => '÷',
+        }
+    }
+
+    /// Name used for worksheet titles and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operator::Add => "addition",
+            Operator::Subtract => "subtraction",
+            Operator::Multiply => "multiplication",
+            Operator::Divide => "division",
+        }
+    }
+
+    pub fn apply(&self, a: i64, b: i64) -> i64 {
+        match self {
+            Operator::Add => a + b,
+            Operator::Subtract => a - b,
+            Operator::Multiply => a * b,
+            // The quotient; long division shows the remainder separately.
+            Operator::Divide => a.checked_div(b).unwrap_or(0),
+        }
+    }
+}
+
+/// Which columns may regroup: carry for addition, borrow for subtraction.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Regrouping {
+    #[default]
+    Any,
+    None,
+    AtLeastOne,
+    /// Only this column regroups, counting from 0 for the ones column.
+    Column(usize),
+}
+
+impl Regrouping {
+    pub fn allows(&self, regroups: &[bool]) -> bool {
+        match self {
+            Regrouping::Any => true,
+            Regrouping::None => !regroups.contains(&true),
+            Regrouping::AtLeastOne => regroups.contains(&true),
+            Regrouping::Column(column) => regroups.iter().enumerate().all(|(i, r)| *r == (i == *column)) && *column < regroups.len(),
+        }
+    }
+}
+
+/// An inclusive range of operand values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OperandRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl OperandRange {
+    pub fn new(min: i64, max: i64) -> OperandRange {
+        OperandRange { min: min.min(max), max: min.max(max) }
+    }
+
+    /// All numbers with exactly `digits` digits, e.g. 10..=99 for two.
+    pub fn digits(digits: u32) -> OperandRange {
+        match digits {
+            0 | 1 => OperandRange::new(0, 9),
+            _ => OperandRange::new(10_i64.pow(digits - 1), 10_i64.pow(digits) - 1),
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> i64 {
+        rng.gen_range(self.min..=self.max)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Problem {
+    pub operator: Operator,
+    pub a: i64,
+    pub b: i64,
+}
+
+/// Drops trailing digits until the operand fits in `MAX_VAL`.
+pub fn prepare_operand(mut operand: i64) -> i64 {
+    while operand.abs() > MAX_VAL {
+        operand /= 10;
+    }
+    operand
+}
+
+/// Decimal digits of a value, least significant first. Zero has one digit.
+pub fn digits_of(value: i64) -> Vec<u8> {
+    let mut value = value.unsigned_abs();
+    let mut digits = vec![(value % 10) as u8];
+    value /= 10;
+    while value > 0 {
+        digits.push((value % 10) as u8);
+        value /= 10;
+    }
+    digits
+}
+
+/// Writes `text` into a row of cells so that it ends at column `right` (exclusive), losing whatever doesn't fit on the left.
+pub fn put_right(cells: &mut Grid<char>, row: usize, right: usize, text: &str) {
+    let length = text.chars().count();
+    let (start, skip) = match right.checked_sub(length) {
+        Some(start) => (start, 0),
+        None => (0, length - right),
+    };
+    for (i, c) in text.chars().skip(skip).enumerate() {
+        cells[(row, start + i)] = c;
+    }
+}
+
+/** A problem drawn on a grid of digit-sized cells, the way it's printed on paper.
+
+`small_rows` hold carries and borrows and are printed in a smaller font. Each rule is a
+horizontal line drawn along the top of `row`, from column `from` up to (not including) `to`.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnLayout {
+    pub cells: Grid<char>,
+    pub small_rows: Vec<usize>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rule {
+    pub row: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+impl ColumnLayout {
+    pub fn blank(rows: usize, cols: usize) -> ColumnLayout {
+        ColumnLayout { cells: Grid::init(rows, cols, ' '), small_rows: vec![], rules: vec![] }
+    }
+
+    /// Plain-text rendering, with `-` lines standing in for rules.
+    #[cfg(test)]
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.cells.rows() {
+            let rules: Vec<&Rule> = self.rules.iter().filter(|r| r.row == row).collect();
+            if !rules.is_empty() {
+                let line: String = (0..self.cells.cols())
+                    .map(|c| if rules.iter().any(|r| c >= r.from && c < r.to) { '-' } else { ' ' })
+                    .collect();
+                out.push_str(line.trim_end());
+                out.push('\n');
+            }
+            let line: String = self.cells.iter_row(row).collect();
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Problem {
+    pub fn new(operator: Operator, a: i64, b: i64) -> Problem {
+        Problem { operator, a: prepare_operand(a), b: prepare_operand(b) }
+    }
+
+    pub fn answer(&self) -> i64 {
+        self.operator.apply(self.a, self.b)
+    }
+
+    /** Columns that regroup, counting from the ones column.
+
+    For addition entry `i` is true when column `i` carries into the next column. For
+    subtraction it's true when column `i` has to borrow from the next column. For
+    multiplication it's true when any partial product carries out of column `i` of the
+    top number. Division never regroups.
+    */
+    pub fn regroups(&self) -> Vec<bool> {
+        let (a, b) = (digits_of(self.a), digits_of(self.b));
+        let width = a.len().max(b.len());
+        let mut regroups = vec![false; width];
+        let mut carry = 0;
+        for (i, regrouped) in regroups.iter_mut().enumerate() {
+            let (da, db) = (a.get(i).copied().unwrap_or(0) as i64, b.get(i).copied().unwrap_or(0) as i64);
+            match self.operator {
+                Operator::Add => {
+                    carry = (da + db + carry) / 10;
+                    *regrouped = carry > 0;
+                }
+                Operator::Subtract => {
+                    *regrouped = da - carry < db;
+                    carry = *regrouped as i64;
+                }
+                Operator::Multiply => {
+                    *regrouped = b.iter().any(|d| multiplication::carries(self.a.abs(), *d).get(i + 1).is_some_and(|c| *c > 0));
+                }
+                Operator::Divide => {}
+            }
+        }
+        regroups
+    }
+
+    /** Lays the problem out as in `question()` from `worksheets/grid.html`.
+
+    An empty carry (or borrow) row sits on top, then both operands right-aligned with the
+    operator in the first column, then the answer line. The key fills in the answer and
+    marks a 1 above each column that receives a carried or borrowed ten.
+    */
+    pub fn layout(&self, key: bool) -> ColumnLayout {
+        match self.operator {
+            Operator::Multiply => return multiplication::layout(self, key),
+            Operator::Divide => return division::layout(self, key),
+            _ => {}
+        }
+        let digits = digits_of(self.a).len().max(digits_of(self.b).len());
+        // Room for minus signs, and the operator column to the left.
+        let (a, b, answer) = (self.a.to_string(), self.b.to_string(), self.answer().to_string());
+        let width = (a.len().max(b.len()) + 1).max(answer.len());
+        let mut layout = ColumnLayout::blank(4, width);
+        layout.small_rows.push(0);
+        put_right(&mut layout.cells, 1, width, &a);
+        put_right(&mut layout.cells, 2, width, &b);
+        layout.cells[(2, 0)] = self.operator.symbol();
+        layout.rules.push(Rule { row: 3, from: 0, to: width });
+        if key {
+            for (i, regrouped) in self.regroups().into_iter().enumerate() {
+                // A carry lands on the next column up; a borrowed ten lands on this one.
+                let column = match self.operator {
+                    Operator::Subtract => i,
+                    _ => i + 1,
+                };
+                if regrouped && column < digits {
+                    layout.cells[(0, width - 1 - column)] = '1';
+                }
+            }
+            put_right(&mut layout.cells, 3, width, &answer);
+        }
+        layout
+    }
+}
+
+/// Random problems with each operand drawn from its own range, like `createProblems`.
+pub fn random_problems<R: Rng>(operator: Operator, first: OperandRange, second: OperandRange, count: usize, rng: &mut R) -> Vec<Problem> {
+    (0..count).map(|_| Problem::new(operator, first.sample(rng), second.sample(rng))).collect()
+}
+
+/** Random problems whose regrouping matches `regrouping`.
+
+With `non_negative`, problems with a negative answer are thrown out. Returns an error if
+no matching problem turns up after `MAX_TRIES` draws, which usually means the ranges
+can't satisfy the rules at all (e.g. a borrow in the tens column with one-digit operands).
+*/
+pub fn regrouping_problems<R: Rng>(operator: Operator, first: OperandRange, second: OperandRange, regrouping: Regrouping, non_negative: bool, count: usize, rng: &mut R) -> Result<Vec<Problem>, String> {
+    let mut problems = vec![];
+    while problems.len() < count {
+        let found = (0..MAX_TRIES)
+            .map(|_| Problem::new(operator, first.sample(rng), second.sample(rng)))
+            .find(|p| (!non_negative || p.answer() >= 0) && regrouping.allows(&p.regroups()));
+        match found {
+            Some(problem) => problems.push(problem),
+            None => return Err(format!("no {} problem in these ranges matches {:?}", operator.name(), regrouping)),
+        }
+    }
+    Ok(problems)
+}
+
+/// Every pair from the range in order that `keep` allows, stopping after `count`, like `createProblemsOrdered`.
+pub fn ordered_problems(operator: Operator, range: OperandRange, count: usize, keep: impl Fn(&Problem) -> bool) -> Vec<Problem> {
+    let mut problems = vec![];
+    for i in range.min..=range.max {
+        for j in range.min..=range.max {
+            if problems.len() == count {
+                return problems;
+            }
+            let problem = Problem::new(operator, i, j);
+            if keep(&problem) {
+                problems.push(problem);
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_operand() {
+        assert_eq!(prepare_operand(12), 12);
+        assert_eq!(prepare_operand(999_999), 999_999);
+        assert_eq!(prepare_operand(12_345_678), 123_456);
+        assert_eq!(prepare_operand(-12_345_678), -123_456);
+    }
+
+    #[test]
+    fn test_regroups() {
+        assert_eq!(Problem::new(Operator::Add, 12, 34).regroups(), vec![false, false]);
+        assert_eq!(Problem::new(Operator::Add, 58, 67).regroups(), vec![true, true]);
+        assert_eq!(Problem::new(Operator::Add, 905, 5).regroups(), vec![true, false, false]);
+        assert_eq!(Problem::new(Operator::Subtract, 47, 23).regroups(), vec![false, false]);
+        assert_eq!(Problem::new(Operator::Subtract, 42, 17).regroups(), vec![true, false]);
+        // Borrowing across a zero makes the zero borrow too.
+        assert_eq!(Problem::new(Operator::Subtract, 302, 7).regroups(), vec![true, true, false]);
+        assert_eq!(Problem::new(Operator::Subtract, 519, 240).regroups(), vec![false, true, false]);
+    }
+
+    #[test]
+    fn test_multiplication_regroups() {
+        assert_eq!(Problem::new(Operator::Multiply, 123, 3).regroups(), vec![false, false, false]);
+        assert_eq!(Problem::new(Operator::Multiply, 125, 3).regroups(), vec![true, false, false]);
+        assert_eq!(Problem::new(Operator::Multiply, 123, 45).regroups(), vec![true, true, false]);
+    }
+
+    #[test]
+    fn test_digit_ranges() {
+        assert_eq!(OperandRange::digits(1), OperandRange::new(0, 9));
+        assert_eq!(OperandRange::digits(3), OperandRange::new(100, 999));
+    }
+
+    #[test]
+    fn test_regrouping_allows() {
+        let (none, ones, tens) = ([false, false], [true, false], [false, true]);
+        assert!(Regrouping::None.allows(&none) && !Regrouping::None.allows(&ones));
+        assert!(Regrouping::AtLeastOne.allows(&tens) && !Regrouping::AtLeastOne.allows(&none));
+        assert!(Regrouping::Column(1).allows(&tens) && !Regrouping::Column(1).allows(&ones));
+        assert!(!Regrouping::Column(1).allows(&[true, true]));
+        assert!(!Regrouping::Column(2).allows(&none));
+    }
+
+    #[test]
+    fn test_regrouping_problems() {
+        let mut rng = rand::thread_rng();
+        let range = OperandRange::new(10, 999);
+        for regrouping in [Regrouping::None, Regrouping::AtLeastOne, Regrouping::Column(0), Regrouping::Column(1)] {
+            let problems = regrouping_problems(Operator::Subtract, range, range, regrouping, true, 30, &mut rng).unwrap();
+            assert_eq!(problems.len(), 30);
+            assert!(problems.iter().all(|p| p.answer() >= 0 && regrouping.allows(&p.regroups())));
+        }
+        let tiny = OperandRange::new(0, 9);
+        assert!(regrouping_problems(Operator::Subtract, tiny, tiny, Regrouping::Column(1), true, 1, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_subtraction_layout() {
+        let problem = Problem::new(Operator::Subtract, 302, 7);
+        assert_eq!(problem.layout(false).to_text(), "\n 302\n-  7\n----\n\n");
+        assert_eq!(problem.layout(true).to_text(), "  11\n 302\n-  7\n----\n 295\n");
+        // Minus signs get their own column.
+        assert_eq!(Problem::new(Operator::Subtract, -5, 5).layout(true).to_text(), "\n -5\n- 5\n---\n-10\n");
+        assert_eq!(Problem::new(Operator::Add, 3, -40).layout(true).to_text(), "\n   3\n+-40\n----\n -37\n");
+    }
+
+    #[test]
+    fn test_layout() {
+        let problem = Problem::new(Operator::Add, 58, 167);
+        assert_eq!(problem.layout(false).to_text(), "\n  58\n+167\n----\n\n");
+        assert_eq!(problem.layout(true).to_text(), " 11\n  58\n+167\n----\n 225\n");
+    }
+
+    #[test]
+    fn test_generation() {
+        let mut rng = rand::thread_rng();
+        let problems = random_problems(Operator::Add, OperandRange::new(3, 20), OperandRange::new(20, 3), 50, &mut rng);
+        assert_eq!(problems.len(), 50);
+        assert!(problems.iter().all(|p| (3..=20).contains(&p.a) && (3..=20).contains(&p.b)));
+
+        let ordered = ordered_problems(Operator::Add, OperandRange::new(1, 3), 5, |_| true);
+        let pairs: Vec<(i64, i64)> = ordered.iter().map(|p| (p.a, p.b)).collect();
+        assert_eq!(pairs, vec![(1, 1), (1, 2), (1, 3), (2, 1), (2, 2)]);
+        assert_eq!(ordered_problems(Operator::Add, OperandRange::new(1, 3), 100, |_| true).len(), 9);
+        let ordered = ordered_problems(Operator::Subtract, OperandRange::new(1, 3), 100, |p| p.answer() >= 0);
+        let pairs: Vec<(i64, i64)> = ordered.iter().map(|p| (p.a, p.b)).collect();
+        assert_eq!(pairs, vec![(1, 1), (2, 1), (2, 2), (3, 1), (3, 2), (3, 3)]);
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::arithmetic::{digits_of, OperandRange, Operator, Problem, Regrouping, MAX_TRIES};
+
+/// Below this many operand pairs every pair is checked, so impossible constraints are reported exactly.
+const ENUMERATE_LIMIT: usize = 250_000;
+
+/// The digits an operand must have, most significant first; `None` is any digit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DigitPattern(Vec<Option<u8>>);
+
+impl DigitPattern {
+    /// Parses patterns like `1?` (the teens) or `??0` (three-digit multiples of ten).
+    pub fn parse(pattern: &str) -> Result<DigitPattern, String> {
+        let digits = pattern
+            .chars()
+            .map(|c| match c {
+                '?' => Ok(None),
+                _ => c.to_digit(10).map(|d| Some(d as u8)).ok_or(format!("bad digit pattern {:?}: use digits and ?", pattern)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if digits.is_empty() {
+            return Err(String::from("empty digit pattern"));
+        }
+        Ok(DigitPattern(digits))
+    }
+
+    pub fn matches(&self, value: i64) -> bool {
+        let digits: Vec<u8> = digits_of(value).into_iter().rev().collect();
+        value >= 0 && digits.len() == self.0.len() && digits.iter().zip(&self.0).all(|(d, p)| p.is_none_or(|p| p == *d))
+    }
+}
+
+/** Declarative rules a worksheet's problems must follow.
+
+`carries` bounds how many columns regroup (see `Problem::regroups`), `result` bounds the
+answer, and `patterns` fix digits of the first and second operand. With `distinct` no
+problem appears twice, and `no_trivial` keeps 0 and 1 out of the operands.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Constraints {
+    pub regrouping: Regrouping,
+    pub carries: Option<(usize, usize)>,
+    pub result: Option<OperandRange>,
+    pub distinct: bool,
+    pub no_trivial: bool,
+    pub patterns: [Option<DigitPattern>; 2],
+}
+
+/// Parses `N` or `MIN..MAX`.
+fn parse_range(text: &str) -> Result<(i64, i64), String> {
+    let bad = || format!("bad range {:?}: use N or MIN..MAX", text);
+    match text.split_once("..") {
+        Some((min, max)) => Ok((min.parse().map_err(|_| bad())?, max.parse().map_err(|_| bad())?)),
+        None => text.parse().map(|n| (n, n)).map_err(|_| bad()),
+    }
+}
+
+impl Constraints {
+    /** Reads one command-line constraint into `self`.
+
+    Understands `carries=N`, `carries=MIN..MAX`, `result=MIN..MAX`, `pattern1=1?`,
+    `pattern2=??0`, `distinct` and `no-trivial`. Returns false for anything else.
+    */
+    pub fn parse_arg(&mut self, arg: &str) -> Result<bool, String> {
+        match arg.split_once('=') {
+            None if arg == "distinct" => self.distinct = true,
+            None if arg == "no-trivial" => self.no_trivial = true,
+            Some(("carries", range)) => {
+                let (min, max) = parse_range(range)?;
+                self.carries = Some((min.max(0) as usize, max.max(0) as usize));
+            }
+            Some(("result", range)) => {
+                let (min, max) = parse_range(range)?;
+                self.result = Some(OperandRange::new(min, max));
+            }
+            Some(("pattern1", pattern)) => self.patterns[0] = Some(DigitPattern::parse(pattern)?),
+            Some(("pattern2", pattern)) => self.patterns[1] = Some(DigitPattern::parse(pattern)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn allows_operand(&self, index: usize, value: i64) -> bool {
+        !(self.no_trivial && (value == 0 || value == 1)) && self.patterns[index].as_ref().is_none_or(|p| p.matches(value))
+    }
+
+    /// Whether a problem follows every rule except `distinct`.
+    pub fn allows(&self, problem: &Problem) -> bool {
+        let regroups = problem.regroups();
+        let carries = regroups.iter().filter(|r| **r).count();
+        self.allows_operand(0, problem.a)
+            && self.allows_operand(1, problem.b)
+            && self.regrouping.allows(&regroups)
+            && self.carries.is_none_or(|(min, max)| (min..=max).contains(&carries))
+            && self.result.is_none_or(|r| (r.min..=r.max).contains(&problem.answer()))
+    }
+}
+
+/** Random problems that satisfy `constraints`, with operands drawn from their ranges.
+
+When the ranges are small enough every pair is checked up front, so an impossible set of
+constraints (or too few distinct problems) is reported exactly. Larger ranges are sampled
+instead, giving up after `MAX_TRIES` draws for any one problem.
+*/
+pub fn constrained_problems<R: Rng>(operator: Operator, first: OperandRange, second: OperandRange, constraints: &Constraints, count: usize, rng: &mut R) -> Result<Vec<Problem>, String> {
+    let firsts: Vec<i64> = (first.min..=first.max).filter(|a| constraints.allows_operand(0, *a)).collect();
+    let seconds: Vec<i64> = (second.min..=second.max).filter(|b| constraints.allows_operand(1, *b)).collect();
+    if firsts.is_empty() || seconds.is_empty() {
+        return Err(format!("no {} operand in its range fits the constraints", if firsts.is_empty() { "first" } else { "second" }));
+    }
+    if firsts.len().saturating_mul(seconds.len()) <= ENUMERATE_LIMIT {
+        let mut all: Vec<Problem> = firsts
+            .iter()
+            .flat_map(|a| seconds.iter().map(move |b| Problem::new(operator, *a, *b)))
+            .filter(|p| constraints.allows(p))
+            .collect();
+        if all.is_empty() {
+            return Err(format!("no {} problem satisfies the constraints", operator.name()));
+        }
+        if constraints.distinct {
+            if all.len() < count {
+                return Err(format!("only {} distinct {} problems satisfy the constraints, {} wanted", all.len(), operator.name(), count));
+            }
+            all.shuffle(rng);
+            all.truncate(count);
+            return Ok(all);
+        }
+        return Ok((0..count).map(|_| *all.choose(rng).unwrap()).collect());
+    }
+    let mut seen = HashSet::new();
+    let mut problems = vec![];
+    while problems.len() < count {
+        let found = (0..MAX_TRIES)
+            .map(|_| Problem::new(operator, *firsts.choose(rng).unwrap(), *seconds.choose(rng).unwrap()))
+            .find(|p| constraints.allows(p) && !(constraints.distinct && seen.contains(&(p.a, p.b))));
+        match found {
+            Some(problem) => {
+                seen.insert((problem.a, problem.b));
+                problems.push(problem);
+            }
+            None => return Err(format!("no {} problem satisfying the constraints turned up in {} draws", operator.name(), MAX_TRIES)),
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Constraints {
+        let mut constraints = Constraints::default();
+        for arg in args {
+            assert!(constraints.parse_arg(arg).unwrap(), "{} not understood", arg);
+        }
+        constraints
+    }
+
+    #[test]
+    fn test_digit_pattern() {
+        let teens = DigitPattern::parse("1?").unwrap();
+        assert!(teens.matches(10) && teens.matches(19));
+        assert!(!teens.matches(9) && !teens.matches(20) && !teens.matches(100));
+        assert!(DigitPattern::parse("??0").unwrap().matches(250));
+        assert!(DigitPattern::parse("1x").is_err());
+    }
+
+    #[test]
+    fn test_parse_arg() {
+        let constraints = parse(&["carries=2", "result=0..20", "distinct", "no-trivial", "pattern2=?5"]);
+        assert_eq!(constraints.carries, Some((2, 2)));
+        assert_eq!(constraints.result, Some(OperandRange::new(0, 20)));
+        assert!(constraints.distinct && constraints.no_trivial);
+        assert_eq!(constraints.patterns[1], Some(DigitPattern(vec![None, Some(5)])));
+        assert!(!Constraints::default().parse_arg("out.pdf").unwrap());
+        assert!(Constraints::default().parse_arg("carries=x").is_err());
+    }
+
+    #[test]
+    fn test_constrained_problems() {
+        let mut rng = rand::thread_rng();
+        let range = OperandRange::new(0, 99);
+        let constraints = parse(&["carries=2", "distinct", "no-trivial"]);
+        let problems = constrained_problems(Operator::Add, range, range, &constraints, 40, &mut rng).unwrap();
+        assert_eq!(problems.len(), 40);
+        assert!(problems.iter().all(|p| p.regroups() == vec![true, true] && p.a > 1 && p.b > 1));
+        assert_eq!(problems.iter().map(|p| (p.a, p.b)).collect::<HashSet<_>>().len(), 40);
+
+        let small = parse(&["result=0..20", "distinct"]);
+        let problems = constrained_problems(Operator::Add, range, range, &small, 20, &mut rng).unwrap();
+        assert!(problems.iter().all(|p| p.answer() <= 20));
+        // Operands from 0 to 2 make only 9 problems.
+        let tiny = OperandRange::new(0, 2);
+        assert!(constrained_problems(Operator::Add, tiny, tiny, &small, 10, &mut rng).unwrap_err().contains("only 9"));
+        // Two carries can't happen with one-digit operands.
+        let digit = OperandRange::new(0, 9);
+        assert!(constrained_problems(Operator::Add, digit, digit, &constraints, 1, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_sampled_problems() {
+        let mut rng = rand::thread_rng();
+        let range = OperandRange::new(1000, 99_999);
+        let constraints = parse(&["pattern1=1???", "carries=0", "distinct"]);
+        let problems = constrained_problems(Operator::Add, range, range, &constraints, 10, &mut rng).unwrap();
+        assert!(problems.iter().all(|p| (1000..=1999).contains(&p.a) && !p.regroups().contains(&true)));
+    }
+}
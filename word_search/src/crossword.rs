@@ -0,0 +1,290 @@
+use grid::Grid;
+use rand::seq::SliceRandom;
+
+use crate::{combine, to_grid, Candidate, Character, Direction, Placement};
+
+const CROSSWORD_DIRS: [Direction; 2] = [Direction::EE, Direction::SS];
+
+/// Stop backtracking after trying this many placements.
+const MAX_ATTEMPTS: usize = 20_000;
+
+/// A numbered clue and the word it answers.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub number: usize,
+    pub placement: Placement,
+    pub clue: String,
+}
+
+pub struct Crossword {
+    pub grid: Grid<Character>,
+    pub across: Vec<Entry>,
+    pub down: Vec<Entry>,
+}
+
+/** Checks the crossword rules that `convolve` doesn't know about.
+
+A new letter may not sit beside another letter across the word's direction, and the
+cells just before and after the word must be empty, so every run of letters in the
+finished grid is exactly one placed word.
+*/
+fn fits(grid: &Grid<Character>, placement: &Placement) -> bool {
+    let (ri, ci) = placement.dir.step();
+    let cells = placement.cells();
+    let is_letter = |r: isize, c: isize| grid.get(r, c).is_some_and(|cell| !cell.is_empty());
+    let (first, last) = (cells[0], cells[cells.len() - 1]);
+    if is_letter(first.0 as isize - ri, first.1 as isize - ci) || is_letter(last.0 as isize + ri, last.1 as isize + ci) {
+        return false;
+    }
+    for (r, c) in cells {
+        if !grid[(r, c)].is_empty() {
+            continue;
+        }
+        let (r, c) = (r as isize, c as isize);
+        if is_letter(r + ci, c + ri) || is_letter(r - ci, c - ri) {
+            return false;
+        }
+    }
+    true
+}
+
+fn place_connected(grid: &Grid<Character>, words: &[String], index: usize, placements: &mut Vec<Placement>, attempts: &mut usize) -> Option<Grid<Character>> {
+    if index == words.len() {
+        return Some(grid.clone());
+    }
+    let word = &words[index];
+    let mut options: Vec<(f32, Candidate, (usize, usize))> = vec![];
+    for candidate in Candidate::create(grid, word, &CROSSWORD_DIRS) {
+        for ((row, col), score) in candidate.placements.indexed_iter() {
+            // Each crossing doubles the score, so anything under 2 doesn't touch the layout.
+            if *score < 2.0 {
+                continue;
+            }
+            if fits(grid, &Placement::from_offset(word, &candidate.dir, row, col)) {
+                options.push((*score, candidate.clone(), (row, col)));
+            }
+        }
+    }
+    options.shuffle(&mut rand::thread_rng());
+    options.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    for (_, candidate, (row, col)) in options {
+        *attempts += 1;
+        if *attempts > MAX_ATTEMPTS {
+            return None;
+        }
+        let mut next = grid.clone();
+        combine(&mut next, &candidate.as_grid, row, col);
+        placements.push(Placement::from_offset(word, &candidate.dir, row, col));
+        if let Some(done) = place_connected(&next, words, index + 1, placements, attempts) {
+            return Some(done);
+        }
+        placements.pop();
+    }
+    None
+}
+
+/// Shrinks the grid to the rows and columns that hold letters and shifts placements to match.
+fn crop(grid: &Grid<Character>, placements: &mut [Placement]) -> Grid<Character> {
+    let letters: Vec<(usize, usize)> = grid.indexed_iter().filter(|(_, c)| !c.is_empty()).map(|(rc, _)| rc).collect();
+    let top = letters.iter().map(|rc| rc.0).min().unwrap_or(0);
+    let left = letters.iter().map(|rc| rc.1).min().unwrap_or(0);
+    let bottom = letters.iter().map(|rc| rc.0 + 1).max().unwrap_or(0);
+    let right = letters.iter().map(|rc| rc.1 + 1).max().unwrap_or(0);
+    let mut out = Grid::init(bottom - top, right - left, Character::default());
+    for ((row, col), cell) in out.indexed_iter_mut() {
+        *cell = grid[(row + top, col + left)];
+    }
+    for placement in placements.iter_mut() {
+        placement.row -= top;
+        placement.col -= left;
+    }
+    out
+}
+
+/** Lays words out across and down so that every word crosses the ones already placed.
+
+Longer words go first. Returns the cropped grid and where each word landed, or None if
+no connected layout fits in a `size` x `size` grid.
+*/
+pub fn layout(words: &[String], size: usize) -> Option<(Grid<Character>, Vec<Placement>)> {
+    let mut words = words.to_vec();
+    words.sort_by_key(|w| std::cmp::Reverse(w.len()));
+    let first = words.first()?;
+    if first.len() > size {
+        return None;
+    }
+    // The first word goes across the middle; everything else has to hang off it.
+    let mut grid = Grid::init(size, size, Character::default());
+    let (row, col) = (size / 2, (size - first.len()) / 2);
+    combine(&mut grid, &to_grid(first, Direction::EE), row, col);
+    let mut placements = vec![Placement::from_offset(first, &Direction::EE, row, col)];
+    let mut attempts = 0;
+    let done = place_connected(&grid, &words, 1, &mut placements, &mut attempts)?;
+    let done = crop(&done, &mut placements);
+    Some((done, placements))
+}
+
+/// Assigns clue numbers in reading order, one per distinct starting cell.
+pub fn number(placements: &[Placement]) -> Vec<(usize, Placement)> {
+    let mut starts: Vec<(usize, usize)> = placements.iter().map(|p| (p.row, p.col)).collect();
+    starts.sort();
+    starts.dedup();
+    let mut numbered: Vec<(usize, Placement)> = placements
+        .iter()
+        .map(|p| (starts.iter().position(|s| *s == (p.row, p.col)).unwrap() + 1, p.clone()))
+        .collect();
+    numbered.sort_by_key(|(n, _)| *n);
+    numbered
+}
+
+impl Crossword {
+    /// Builds a crossword from `(answer, clue)` pairs. Answers should already be cleaned up.
+    pub fn generate(clues: &[(String, String)], size: usize) -> Option<Crossword> {
+        let words: Vec<String> = clues.iter().map(|(w, _)| w.clone()).collect();
+        let (grid, placements) = layout(&words, size)?;
+        let mut across = vec![];
+        let mut down = vec![];
+        for (number, placement) in number(&placements) {
+            let clue = clues.iter().find(|(w, _)| *w == placement.word).map(|(_, c)| c.clone()).unwrap_or_default();
+            let entry = Entry { number, placement, clue };
+            match entry.placement.dir {
+                Direction::EE => across.push(entry),
+                _ => down.push(entry),
+            }
+        }
+        Some(Crossword { grid, across, down })
+    }
+
+    fn number_at(&self, row: usize, col: usize) -> Option<usize> {
+        self.across.iter().chain(self.down.iter())
+            .find(|e| e.placement.row == row && e.placement.col == col)
+            .map(|e| e.number)
+    }
+
+    /// The puzzle as handed out: black squares as `####`, white squares carrying their clue number.
+    pub fn render_blank(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.grid.rows() {
+            for col in 0..self.grid.cols() {
+                if self.grid[(row, col)].is_empty() {
+                    out.push_str("####");
+                } else {
+                    match self.number_at(row, col) {
+                        Some(n) => out.push_str(&format!("[{:>2}]", n)),
+                        None => out.push_str("[  ]"),
+                    }
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn render_clues(&self) -> String {
+        let mut out = String::from("ACROSS\n");
+        for entry in &self.across {
+            out.push_str(&format!("{:>3}. {} ({})\n", entry.number, entry.clue, entry.placement.word.len()));
+        }
+        out.push_str("\nDOWN\n");
+        for entry in &self.down {
+            out.push_str(&format!("{:>3}. {} ({})\n", entry.number, entry.clue, entry.placement.word.len()));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(list: &[&str]) -> Vec<String> {
+        list.iter().map(|w| w.to_string()).collect()
+    }
+
+    /// Every horizontal or vertical run of two or more letters must be a placed word.
+    fn assert_runs_are_words(grid: &Grid<Character>, placements: &[Placement]) {
+        for dir in CROSSWORD_DIRS {
+            let (ri, ci) = dir.step();
+            for ((row, col), cell) in grid.indexed_iter() {
+                let before = grid.get(row as isize - ri, col as isize - ci).is_some_and(|c| !c.is_empty());
+                if cell.is_empty() || before {
+                    continue;
+                }
+                let mut run = String::new();
+                let (mut r, mut c) = (row as isize, col as isize);
+                while let Some(next) = grid.get(r, c).filter(|c| !c.is_empty()) {
+                    run.push(next.letter);
+                    r += ri;
+                    c += ci;
+                }
+                if run.len() > 1 {
+                    assert!(placements.iter().any(|p| p.word == run && p.dir == dir && (p.row, p.col) == (row, col)), "stray run {}", run);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout_is_connected() {
+        let list = words(&["ELEPHANT", "TIGER", "HIPPO", "ZEBRA", "OTTER", "LION"]);
+        for _ in 0..10 {
+            let (grid, placements) = layout(&list, 15).unwrap();
+            assert_eq!(placements.len(), list.len());
+            assert_runs_are_words(&grid, &placements);
+            for p in &placements {
+                for (i, cell) in p.cells().into_iter().enumerate() {
+                    assert_eq!(grid[cell].letter, p.word.as_bytes()[i] as char);
+                }
+            }
+            // Flood fill from the first word should reach every letter.
+            let mut seen = vec![placements[0].clone()];
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for p in &placements {
+                    let crosses = seen.iter().any(|s| s.cells().iter().any(|c| p.cells().contains(c)));
+                    if crosses && !seen.contains(p) {
+                        seen.push(p.clone());
+                        changed = true;
+                    }
+                }
+            }
+            assert_eq!(seen.len(), placements.len());
+        }
+    }
+
+    #[test]
+    fn test_layout_impossible() {
+        assert!(layout(&words(&["AAA", "BBB"]), 10).is_none());
+    }
+
+    #[test]
+    fn test_number() {
+        let across = Placement { word: "CAT".to_string(), row: 0, col: 0, dir: Direction::EE };
+        let down = Placement { word: "CUP".to_string(), row: 0, col: 0, dir: Direction::SS };
+        let down2 = Placement { word: "TOE".to_string(), row: 0, col: 2, dir: Direction::SS };
+        let across2 = Placement { word: "PIE".to_string(), row: 2, col: 0, dir: Direction::EE };
+        let numbered = number(&[across2.clone(), down2.clone(), down.clone(), across.clone()]);
+        let numbers: Vec<(usize, &str)> = numbered.iter().map(|(n, p)| (*n, p.word.as_str())).collect();
+        assert!(numbers.contains(&(1, "CAT")));
+        assert!(numbers.contains(&(1, "CUP")));
+        assert!(numbers.contains(&(2, "TOE")));
+        assert!(numbers.contains(&(3, "PIE")));
+    }
+
+    #[test]
+    fn test_render() {
+        let clues = vec![
+            ("CAT".to_string(), "Says meow".to_string()),
+            ("TOE".to_string(), "On your foot".to_string()),
+        ];
+        let crossword = Crossword::generate(&clues, 10).unwrap();
+        assert_eq!(crossword.across.len() + crossword.down.len(), 2);
+        let blank = crossword.render_blank();
+        assert!(blank.contains("[ 1]"));
+        assert!(!blank.contains('C'));
+        let text = crossword.render_clues();
+        assert!(text.contains("Says meow (3)"));
+        assert!(text.contains("On your foot (3)"));
+    }
+}
@@ -0,0 +1,142 @@
+use rand::Rng;
+
+use crate::arithmetic::{digits_of, put_right, ColumnLayout, OperandRange, Operator, Problem, Rule, MAX_TRIES};
+
+/// One subtract-and-bring-down step, for the quotient digit over dividend digit `column` (from the left).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub column: usize,
+    pub product: i64,
+    pub difference: i64,
+}
+
+/// Worked steps of the long division `a` / `b`, starting from the first digit the divisor goes into.
+pub fn steps(a: i64, b: i64) -> Vec<Step> {
+    let digits: Vec<u8> = digits_of(a).into_iter().rev().collect();
+    let mut steps = vec![];
+    let mut current = 0;
+    for (column, digit) in digits.iter().enumerate() {
+        current = current * 10 + *digit as i64;
+        if steps.is_empty() && current < b && column + 1 < digits.len() {
+            continue;
+        }
+        let product = current / b * b;
+        steps.push(Step { column, product, difference: current - product });
+        current -= product;
+    }
+    steps
+}
+
+/// Rows and columns of the largest layout for these digit counts, for fitting a page.
+pub fn size(dividend_digits: usize, divisor_digits: usize) -> (usize, usize) {
+    (2 + 2 * dividend_digits, divisor_digits + 1 + dividend_digits + 2 + divisor_digits)
+}
+
+/** Lays out long division in bracket notation.
+
+The quotient goes on the top row, over the bracket line, and the divisor sits left of the
+`)` on the dividend row. Below that are two rows for every step: the product being taken
+away and the difference with the next digit brought down. The worksheet leaves those rows
+blank for the working; the key fills them in along with the quotient and any remainder.
+*/
+pub fn layout(problem: &Problem, key: bool) -> ColumnLayout {
+    let (a, b) = (problem.a.abs(), problem.b.abs().max(1));
+    let (da, db) = (digits_of(a).len(), digits_of(b).len());
+    let steps = steps(a, b);
+    let remainder = if a % b == 0 { String::new() } else { format!(" r{}", a % b) };
+    // The dividend starts right after the divisor and its bracket.
+    let left = db + 1;
+    let width = left + da + remainder.len();
+    let mut layout = ColumnLayout::blank(2 + 2 * steps.len(), width);
+    put_right(&mut layout.cells, 1, db, &b.to_string());
+    layout.cells[(1, db)] = ')';
+    put_right(&mut layout.cells, 1, left + da, &a.to_string());
+    layout.rules.push(Rule { row: 1, from: left, to: left + da });
+    if key {
+        put_right(&mut layout.cells, 0, width, &format!("{}{}", a / b, remainder));
+        for (i, step) in steps.iter().enumerate() {
+            let (product_row, difference_row) = (2 + 2 * i, 3 + 2 * i);
+            let end = left + step.column + 1;
+            let product = step.product.to_string();
+            let start = end - product.len();
+            put_right(&mut layout.cells, product_row, end, &product);
+            layout.cells[(product_row, start - 1)] = '-';
+            layout.rules.push(Rule { row: difference_row, from: start - 1, to: end });
+            match digits_of(a).iter().rev().nth(step.column + 1) {
+                Some(next) => put_right(&mut layout.cells, difference_row, end + 1, &(step.difference * 10 + *next as i64).to_string()),
+                None => put_right(&mut layout.cells, difference_row, end, &step.difference.to_string()),
+            }
+        }
+    }
+    layout
+}
+
+/** Random division problems with dividends and divisors drawn from their ranges.
+
+Without `remainders` every problem divides exactly. Divisors are at least 1, and dividends
+are never smaller than their divisor. Returns an error when the ranges leave no such
+problem, e.g. a one-digit dividend with a two-digit divisor.
+*/
+pub fn division_problems<R: Rng>(dividend: OperandRange, divisor: OperandRange, remainders: bool, count: usize, rng: &mut R) -> Result<Vec<Problem>, String> {
+    let divisor = OperandRange::new(divisor.min.max(1), divisor.max.max(1));
+    let mut problems = vec![];
+    while problems.len() < count {
+        let found = (0..MAX_TRIES).find_map(|_| {
+            let b = divisor.sample(rng);
+            let a = if remainders {
+                dividend.sample(rng)
+            } else {
+                // Pick the quotient instead, so the dividend is a multiple of the divisor.
+                let (low, high) = ((dividend.min + b - 1) / b, dividend.max / b);
+                if low > high {
+                    return None;
+                }
+                rng.gen_range(low..=high) * b
+            };
+            (a >= b).then(|| Problem::new(Operator::Divide, a, b))
+        });
+        match found {
+            Some(problem) => problems.push(problem),
+            None => return Err(String::from("no division problem in these ranges fits")),
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps() {
+        let steps = steps(745, 3);
+        assert_eq!(steps.iter().map(|s| (s.column, s.product, s.difference)).collect::<Vec<_>>(), vec![(0, 6, 1), (1, 12, 2), (2, 24, 1)]);
+        // 3 doesn't go into 1, so the first step is over the 2.
+        assert_eq!(super::steps(128, 3)[0], Step { column: 1, product: 12, difference: 0 });
+        // A zero in the quotient still gets a step.
+        assert_eq!(super::steps(612, 6).len(), 3);
+    }
+
+    #[test]
+    fn test_layout() {
+        let problem = Problem::new(Operator::Divide, 745, 3);
+        assert_eq!(problem.answer(), 248);
+        let key = problem.layout(true);
+        assert_eq!(key.to_text(), "  248 r1\n  ---\n3)745\n -6\n --\n  14\n -12\n ---\n   25\n  -24\n  ---\n    1\n");
+        let blank = problem.layout(false);
+        assert_eq!(blank.to_text(), format!("\n  ---\n3)745\n{}", "\n".repeat(6)));
+        assert_eq!(blank.cells.size(), key.cells.size());
+        let (rows, cols) = size(3, 1);
+        assert!(key.cells.rows() <= rows && key.cells.cols() <= cols);
+    }
+
+    #[test]
+    fn test_division_problems() {
+        let mut rng = rand::thread_rng();
+        let exact = division_problems(OperandRange::digits(3), OperandRange::digits(1), false, 40, &mut rng).unwrap();
+        assert!(exact.iter().all(|p| p.b >= 1 && p.a % p.b == 0 && (100..=999).contains(&p.a)));
+        let any = division_problems(OperandRange::digits(3), OperandRange::digits(2), true, 40, &mut rng).unwrap();
+        assert!(any.iter().all(|p| p.a >= p.b && (10..=99).contains(&p.b)));
+        assert!(division_problems(OperandRange::digits(1), OperandRange::digits(2), true, 1, &mut rng).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::arithmetic::{digits_of, ColumnLayout, OperandRange, Operator, Problem};
+
+/** A family of math facts for timed drills, e.g. the ×6 facts or +0 through +10.
+
+Every value in `family` is combined with every value in `others`. A fact and its commuted
+twin (6×7 and 7×6) count as one fact. Subtraction and division facts are the inverses,
+so the −6 family is 6−6 through 16−6 as well as 16−10 and so on.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FactSet {
+    pub operator: Operator,
+    pub family: OperandRange,
+    pub others: OperandRange,
+}
+
+impl FactSet {
+    /// Distinct facts as (smaller, larger) pairs of the two numbers that make them.
+    pub fn facts(&self) -> Vec<(i64, i64)> {
+        let mut facts = vec![];
+        for f in self.family.min..=self.family.max {
+            for o in self.others.min..=self.others.max {
+                let pair = (f.min(o), f.max(o));
+                // No dividing by zero, in either order.
+                if self.operator == Operator::Divide && pair.0 == 0 {
+                    continue;
+                }
+                if !facts.contains(&pair) {
+                    facts.push(pair);
+                }
+            }
+        }
+        facts
+    }
+
+    /// The problem for a fact read one way round; `y` is the operand kids are drilling.
+    fn problem(&self, x: i64, y: i64) -> Problem {
+        match self.operator {
+            Operator::Add | Operator::Multiply => Problem::new(self.operator, x, y),
+            Operator::Subtract => Problem::new(self.operator, x + y, y),
+            Operator::Divide => Problem::new(self.operator, x * y, y),
+        }
+    }
+}
+
+/** `count` problems covering the facts as evenly as possible, in shuffled order.
+
+Each fact shows up either `count / facts` times or once more. Each time a fact comes up it
+is written the other way round from the time before, starting from a balanced mix, so
+both orders appear about equally often over the whole sheet.
+*/
+pub fn fact_problems<R: Rng>(set: &FactSet, count: usize, rng: &mut R) -> Vec<Problem> {
+    let facts = set.facts();
+    if facts.is_empty() {
+        return vec![];
+    }
+    let mut flipped: Vec<bool> = (0..facts.len()).map(|i| i % 2 == 0).collect();
+    flipped.shuffle(rng);
+    let mut problems = vec![];
+    while problems.len() < count {
+        let mut order: Vec<usize> = (0..facts.len()).collect();
+        order.shuffle(rng);
+        for i in order.into_iter().take(count - problems.len()) {
+            let (x, y) = facts[i];
+            problems.push(if flipped[i] { set.problem(y, x) } else { set.problem(x, y) });
+            flipped[i] = !flipped[i];
+        }
+    }
+    problems
+}
+
+/// Column widths shared by every problem on a sheet, so the `=` signs line up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FactWidths {
+    pub number: usize,
+    pub operand: usize,
+    pub answer: usize,
+}
+
+impl FactWidths {
+    pub fn of(problems: &[Problem]) -> FactWidths {
+        let len = |v: i64| digits_of(v).len() + (v < 0) as usize;
+        FactWidths {
+            number: len(problems.len() as i64),
+            operand: problems.iter().map(|p| len(p.a).max(len(p.b))).max().unwrap_or(1),
+            answer: problems.iter().map(|p| len(p.answer())).max().unwrap_or(1).max(2),
+        }
+    }
+
+    /// Cells in one line like `12.  7 × 6 = __`.
+    pub fn cols(&self) -> usize {
+        self.number + 3 + self.operand * 2 + 6 + self.answer
+    }
+}
+
+/// One numbered problem on a single line, with a blank to write the answer on (or the answer in the key).
+pub fn layout(number: usize, problem: &Problem, widths: FactWidths, key: bool) -> ColumnLayout {
+    let answer = if key { format!("{:<w$}", problem.answer(), w = widths.answer) } else { "_".repeat(widths.answer) };
+    let text = format!(
+        "{:>nw$}.  {:>ow$} {} {:>ow$} = {}",
+        number,
+        problem.a,
+        problem.operator.symbol(),
+        problem.b,
+        answer,
+        nw = widths.number,
+        ow = widths.operand
+    );
+    let mut layout = ColumnLayout::blank(1, widths.cols());
+    for (i, c) in text.chars().enumerate() {
+        layout.cells[(0, i)] = c;
+    }
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_facts() {
+        let sixes = FactSet { operator: Operator::Multiply, family: OperandRange::new(6, 6), others: OperandRange::new(0, 10) };
+        assert_eq!(sixes.facts().len(), 11);
+        // 6×7 and 7×6 are the same fact.
+        let table = FactSet { operator: Operator::Add, family: OperandRange::new(0, 10), others: OperandRange::new(0, 10) };
+        assert_eq!(table.facts().len(), 66);
+        let division = FactSet { operator: Operator::Divide, ..sixes };
+        assert_eq!(division.facts().len(), 10);
+        assert_eq!(division.problem(7, 6), Problem::new(Operator::Divide, 42, 6));
+        assert_eq!(FactSet { operator: Operator::Subtract, ..sixes }.problem(9, 6).answer(), 9);
+    }
+
+    #[test]
+    fn test_even_coverage() {
+        let set = FactSet { operator: Operator::Multiply, family: OperandRange::new(6, 6), others: OperandRange::new(0, 10) };
+        let problems = fact_problems(&set, 50, &mut rand::thread_rng());
+        assert_eq!(problems.len(), 50);
+        let mut counts: HashMap<(i64, i64), usize> = HashMap::new();
+        let mut sixes_first = 0;
+        for p in &problems {
+            *counts.entry((p.a.min(p.b), p.a.max(p.b))).or_default() += 1;
+            sixes_first += (p.a == 6 && p.b != 6) as usize;
+        }
+        assert_eq!(counts.len(), 11);
+        assert!(counts.values().all(|c| *c == 4 || *c == 5));
+        // 6×6 can't be flipped; the other 45 split evenly, give or take one per fact.
+        assert!((17..=28).contains(&sixes_first), "{}", sixes_first);
+    }
+
+    #[test]
+    fn test_layout() {
+        let problems = vec![Problem::new(Operator::Multiply, 7, 6), Problem::new(Operator::Multiply, 10, 6)];
+        let widths = FactWidths::of(&problems);
+        assert_eq!(layout(2, &problems[0], widths, false).to_text(), "2.   7 ×  6 = __\n");
+        assert_eq!(layout(2, &problems[1], widths, true).to_text(), "2.  10 ×  6 = 60\n");
+        assert_eq!(layout(1, &problems[0], widths, true).cells.cols(), widths.cols());
+    }
+}
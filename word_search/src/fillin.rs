@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+
+use grid::Grid;
+
+use crate::crossword::layout;
+use crate::{Character, Placement, EMPTY};
+
+/// How many fresh layouts to try before giving up on a unique solution.
+const MAX_LAYOUTS: usize = 50;
+
+/// A criss-cross puzzle: the crossword skeleton with no clues, solved by fitting the word bank in.
+pub struct FillIn {
+    pub grid: Grid<Character>,
+    pub placements: Vec<Placement>,
+}
+
+/// Counts ways to fill the slots with the words, each word used once, stopping at `limit`.
+fn count_solutions(letters: &mut Grid<char>, slots: &[Vec<(usize, usize)>], words: &mut Vec<String>, limit: usize) -> usize {
+    let Some((slot, rest)) = slots.split_first() else {
+        return 1;
+    };
+    let mut found = 0;
+    let mut tried: Vec<String> = vec![];
+    for i in 0..words.len() {
+        let word = words[i].clone();
+        // Identical words are interchangeable, so swapping them isn't a new solution.
+        if word.len() != slot.len() || tried.contains(&word) {
+            continue;
+        }
+        tried.push(word.clone());
+        let fits = slot.iter().zip(word.chars()).all(|(cell, c)| letters[*cell] == EMPTY || letters[*cell] == c);
+        if !fits {
+            continue;
+        }
+        let before: Vec<char> = slot.iter().map(|cell| letters[*cell]).collect();
+        for (cell, c) in slot.iter().zip(word.chars()) {
+            letters[*cell] = c;
+        }
+        words.remove(i);
+        found += count_solutions(letters, rest, words, limit - found);
+        words.insert(i, word);
+        for (cell, c) in slot.iter().zip(before) {
+            letters[*cell] = c;
+        }
+        if found >= limit {
+            break;
+        }
+    }
+    found
+}
+
+impl FillIn {
+    /** Lays the words out as a connected crossword whose word bank has exactly one solution.
+
+    Tries up to `MAX_LAYOUTS` layouts, returning None if none of them is unique.
+    */
+    pub fn generate(words: &[String], size: usize) -> Option<FillIn> {
+        for _ in 0..MAX_LAYOUTS {
+            let (grid, placements) = layout(words, size)?;
+            let fill_in = FillIn { grid, placements };
+            if fill_in.solutions(2) == 1 {
+                return Some(fill_in);
+            }
+        }
+        None
+    }
+
+    /// Counts how many ways the word bank fits the skeleton, up to `limit`.
+    pub fn solutions(&self, limit: usize) -> usize {
+        let mut letters: Grid<char> = Grid::init(self.grid.rows(), self.grid.cols(), EMPTY);
+        let slots: Vec<Vec<(usize, usize)>> = self.placements.iter().map(|p| p.cells()).collect();
+        let mut words: Vec<String> = self.placements.iter().map(|p| p.word.clone()).collect();
+        count_solutions(&mut letters, &slots, &mut words, limit)
+    }
+
+    /// The empty skeleton: black squares as `####`, white squares as `[  ]`.
+    pub fn render_skeleton(&self) -> String {
+        let mut out = String::new();
+        for row in self.grid.iter_rows() {
+            for cell in row {
+                out.push_str(if cell.is_empty() { "####" } else { "[  ]" });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The word bank, grouped by length and sorted within each group.
+    pub fn render_word_bank(&self) -> String {
+        let mut groups: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+        for placement in &self.placements {
+            groups.entry(placement.word.len()).or_default().push(&placement.word);
+        }
+        let mut out = String::new();
+        for (len, mut words) in groups {
+            words.sort();
+            out.push_str(&format!("{} LETTERS\n  {}\n", len, words.join("  ")));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Direction;
+
+    fn fill_in(placements: Vec<Placement>, rows: usize, cols: usize) -> FillIn {
+        let mut grid = Grid::init(rows, cols, Character::default());
+        for p in &placements {
+            for (cell, c) in p.cells().into_iter().zip(p.word.chars()) {
+                grid[cell].letter = c;
+            }
+        }
+        FillIn { grid, placements }
+    }
+
+    #[test]
+    fn test_solutions() {
+        // CAT across, with TOP and CUP hanging down from it.
+        let unique = fill_in(vec![
+            Placement { word: "CAT".to_string(), row: 0, col: 0, dir: Direction::EE },
+            Placement { word: "CUP".to_string(), row: 0, col: 0, dir: Direction::SS },
+            Placement { word: "TOP".to_string(), row: 0, col: 2, dir: Direction::SS },
+        ], 3, 3);
+        assert_eq!(unique.solutions(10), 1);
+
+        // CAT across a single down slot: CAT could go down just as well as across.
+        let ambiguous = fill_in(vec![
+            Placement { word: "CAT".to_string(), row: 0, col: 0, dir: Direction::EE },
+            Placement { word: "COT".to_string(), row: 0, col: 0, dir: Direction::SS },
+        ], 3, 3);
+        assert_eq!(ambiguous.solutions(10), 2);
+        assert_eq!(ambiguous.solutions(1), 1);
+
+        // Repeated words don't count as separate solutions.
+        let repeated = fill_in(vec![
+            Placement { word: "CAT".to_string(), row: 0, col: 0, dir: Direction::EE },
+            Placement { word: "CAT".to_string(), row: 0, col: 0, dir: Direction::SS },
+        ], 3, 3);
+        assert_eq!(repeated.solutions(10), 1);
+    }
+
+    #[test]
+    fn test_generate() {
+        let words: Vec<String> = ["ELEPHANT", "TIGER", "HIPPO", "ZEBRA", "OTTER", "LION", "PIG"]
+            .iter().map(|w| w.to_string()).collect();
+        let fill_in = FillIn::generate(&words, 15).unwrap();
+        assert_eq!(fill_in.solutions(10), 1);
+        let bank = fill_in.render_word_bank();
+        assert!(bank.starts_with("3 LETTERS\n  PIG\n4 LETTERS\n  LION\n5 LETTERS\n  HIPPO  OTTER  TIGER  ZEBRA\n"));
+        let skeleton = fill_in.render_skeleton();
+        assert!(!skeleton.contains('E'));
+        assert_eq!(skeleton.matches("[  ]").count(), fill_in.grid.iter().filter(|c| !c.is_empty()).count());
+    }
+}
@@ -0,0 +1,166 @@
+use grid::Grid;
+use rand::Rng;
+
+use crate::{Character, ALL_DIRS, EMPTY};
+
+const DEFAULT_BLOCKLIST: &str = include_str!("blocklist.txt");
+
+/// Give up re-rolling filler after this many passes over the grid.
+const MAX_REROLLS: usize = 1000;
+
+/// Words that aren't age-appropriate, kept out of word selection and random filler.
+pub struct Blocklist {
+    words: Vec<Vec<char>>,
+}
+
+impl Blocklist {
+    pub fn load(list: &str) -> Blocklist {
+        let words = list
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_ascii_uppercase().chars().collect())
+            .collect();
+        Blocklist { words }
+    }
+
+    /// Returns false if `word` is on the list. Comparison ignores case.
+    pub fn allows(&self, word: &str) -> bool {
+        let word: Vec<char> = word.to_ascii_uppercase().chars().collect();
+        !self.words.contains(&word)
+    }
+
+    /** Scans the grid in all eight directions for blocked words.
+
+    Only occurrences that run through at least one filler cell are reported, since
+    those are the only ones re-rolling can fix. Returns the filler cells involved.
+    */
+    pub fn find_in_filler(&self, grid: &Grid<Character>, filler: &Grid<bool>) -> Vec<(usize, usize)> {
+        let mut hits: Vec<(usize, usize)> = vec![];
+        for ((row, col), _) in grid.indexed_iter() {
+            for dir in &ALL_DIRS {
+                let (ri, ci) = dir.step();
+                for word in &self.words {
+                    let mut cells: Vec<(usize, usize)> = Vec::with_capacity(word.len());
+                    let (mut r, mut c) = (row as isize, col as isize);
+                    for &letter in word {
+                        match grid.get(r, c) {
+                            Some(cell) if cell.letter.to_ascii_uppercase() == letter => {
+                                cells.push((r as usize, c as usize));
+                            }
+                            _ => break,
+                        }
+                        r += ri;
+                        c += ci;
+                    }
+                    if cells.len() == word.len() {
+                        for cell in cells {
+                            if filler[cell] && !hits.contains(&cell) {
+                                hits.push(cell);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        hits
+    }
+}
+
+impl Default for Blocklist {
+    fn default() -> Self {
+        Blocklist::load(DEFAULT_BLOCKLIST)
+    }
+}
+
+fn random_letter<R: Rng>(rng: &mut R) -> char {
+    rng.gen_range(b'A'..=b'Z') as char
+}
+
+/** Fills every empty cell with a random letter, re-rolling filler until no blocked word appears.
+
+Returns false if the grid still spells a blocked word after `MAX_REROLLS` passes.
+*/
+pub fn fill_with_random_letters<R: Rng>(grid: &mut Grid<Character>, blocklist: &Blocklist, rng: &mut R) -> bool {
+    let mut filler: Grid<bool> = Grid::new(grid.rows(), grid.cols());
+    for ((row, col), val) in grid.indexed_iter_mut() {
+        if val.letter == EMPTY {
+            val.letter = random_letter(rng);
+            filler[(row, col)] = true;
+        }
+    }
+    for _ in 0..MAX_REROLLS {
+        let hits = blocklist.find_in_filler(grid, &filler);
+        if hits.is_empty() {
+            return true;
+        }
+        for cell in hits {
+            grid[cell].letter = random_letter(rng);
+        }
+    }
+    blocklist.find_in_filler(grid, &filler).is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gg;
+    use grid::grid;
+
+    #[test]
+    fn test_allows() {
+        let blocklist = Blocklist::load("# comment\nbad\n\n  Worse \n");
+        assert!(!blocklist.allows("bad"));
+        assert!(!blocklist.allows("BAD"));
+        assert!(!blocklist.allows("worse"));
+        assert!(blocklist.allows("badge"));
+        assert!(blocklist.allows("comment"));
+    }
+
+    #[test]
+    fn test_default_blocklist() {
+        let blocklist = Blocklist::default();
+        assert!(!blocklist.allows("kill"));
+        assert!(blocklist.allows("kitten"));
+    }
+
+    #[test]
+    fn test_find_in_filler() {
+        let blocklist = Blocklist::load("bad");
+        let g = gg(grid![
+            ['D','A','B']
+            ['.','X','.']
+            ['B','A','D']
+        ]);
+        let mut filler: Grid<bool> = Grid::new(3, 3);
+        // Bottom row is entirely placed letters and can't be re-rolled.
+        assert!(blocklist.find_in_filler(&g, &filler).is_empty());
+        filler[(0, 0)] = true;
+        assert_eq!(blocklist.find_in_filler(&g, &filler), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_fill_with_random_letters() {
+        // Every two-letter word ending in A is blocked, so filler next to a placed A
+        // can never be cleared.
+        let list: String = ('a'..='z').map(|c| format!("{}a\n", c)).collect();
+        let blocklist = Blocklist::load(&list);
+        let mut rng = rand::thread_rng();
+        let mut g = gg(grid![
+            ['.','.','.']
+            ['.','A','.']
+            ['.','.','.']
+        ]);
+        assert!(!fill_with_random_letters(&mut g, &blocklist, &mut rng));
+        for _ in 0..20 {
+            let mut g = gg(grid![
+                ['.','.','.','.']
+                ['.','.','.','.']
+            ]);
+            assert!(fill_with_random_letters(&mut g, &Blocklist::load("ab\nba\ncd"), &mut rng));
+            let filler: Grid<bool> = Grid::init(2, 4, true);
+            assert!(Blocklist::load("ab\nba\ncd").find_in_filler(&g, &filler).is_empty());
+            assert!(g.iter().all(|c| c.letter.is_ascii_uppercase()));
+        }
+    }
+}
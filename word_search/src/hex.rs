@@ -0,0 +1,347 @@
+use grid::Grid;
+
+use crate::filter::{fill_with_random_letters, Blocklist};
+use crate::{combine, Candidate, Character, Direction, Placement, EMPTY, HEX_DIRS, OFF_BOARD};
+
+/// A word search on a honeycomb, laid out like `worksheets/hex.html`: rows of hexes with
+/// every odd row pushed half a cell to the right.
+///
+/// Letters live in an axial grid so `convolve` can place words along the six hex
+/// directions. The honeycomb only covers part of that grid; the rest is `OFF_BOARD`.
+pub struct HexSearch {
+    pub width: usize,
+    pub height: usize,
+    pub grid: Grid<Character>,
+    pub placements: Vec<Placement>,
+    pub missing: Vec<String>,
+}
+
+/// Maps a honeycomb row and column to its cell in the axial grid.
+pub fn axial(height: usize, row: usize, col: usize) -> (usize, usize) {
+    (row, col + (height - 1) / 2 - row / 2)
+}
+
+/// The hex one step from `cell` in direction `dir` on a `width` x `height` honeycomb, if there is one.
+pub fn step(width: usize, height: usize, cell: (usize, usize), dir: &Direction) -> Option<(usize, usize)> {
+    let offset = (height - 1) / 2;
+    let (arow, acol) = axial(height, cell.0, cell.1);
+    let (dr, dc) = dir.step();
+    let r = arow.checked_add_signed(dr)?;
+    let c = (acol.checked_add_signed(dc)? + r / 2).checked_sub(offset)?;
+    (r < height && c < width).then_some((r, c))
+}
+
+/// Honeycomb rows as HTML, with `cell` giving each hex's text and CSS class ("" for none).
+pub fn honeycomb(width: usize, height: usize, cell: impl Fn(usize, usize) -> (String, String)) -> String {
+    let mut out = String::from("        <div class=\"container\">\n");
+    for row in 0..height {
+        let class = if row % 2 == 1 { "hex-row odd" } else { "hex-row" };
+        out.push_str(&format!("            <div class=\"{}\">", class));
+        for col in 0..width {
+            let (text, class) = cell(row, col);
+            let class = if class.is_empty() { class } else { format!(" class=\"{}\"", class) };
+            out.push_str(&format!("<div{}>{}</div>", class, text));
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str("        </div>\n");
+    out
+}
+
+fn blank_board(width: usize, height: usize) -> Grid<Character> {
+    let mut grid = Grid::init(height, width + (height - 1) / 2, Character::from(OFF_BOARD));
+    for row in 0..height {
+        for col in 0..width {
+            grid[axial(height, row, col)].letter = EMPTY;
+        }
+    }
+    grid
+}
+
+/// Compass heading of a hex direction as it looks on the printed honeycomb.
+pub fn compass(dir: &Direction) -> &'static str {
+    match dir {
+        Direction::EE => "E",
+        Direction::WW => "W",
+        Direction::NN => "NW",
+        Direction::SS => "SE",
+        Direction::NE => "NE",
+        Direction::SW => "SW",
+        _ => "?",
+    }
+}
+
+impl HexSearch {
+    /// Places as many words as fit on a `width` x `height` honeycomb and fills the rest with letters.
+    ///
+    /// Words go in longest first, each at one of its best-scoring spots; a word with no room
+    /// left is skipped and listed in `missing` rather than holding up the others.
+    pub fn generate(words: &[String], width: usize, height: usize, blocklist: &Blocklist) -> HexSearch {
+        let mut words = words.to_vec();
+        words.sort_by_key(|w| std::cmp::Reverse(w.len()));
+        let mut grid = blank_board(width, height);
+        let mut placements = vec![];
+        let mut missing = vec![];
+        for word in words {
+            let mut candidates = Candidate::create(&grid, &word, &HEX_DIRS);
+            candidates.sort_by(|a, b| b.max_placement_value.partial_cmp(&a.max_placement_value).unwrap());
+            match candidates.first().and_then(|c| Some((c, *c.max_placements.first()?))) {
+                Some((candidate, (row, col))) => {
+                    combine(&mut grid, &candidate.as_grid, row, col);
+                    placements.push(Placement::from_offset(&word, &candidate.dir, row, col));
+                }
+                None => missing.push(word),
+            }
+        }
+        if !fill_with_random_letters(&mut grid, blocklist, &mut rand::thread_rng()) {
+            println!("warning: couldn't re-roll filler clear of blocked words");
+        }
+        HexSearch { width, height, grid, placements, missing }
+    }
+
+    fn letter(&self, row: usize, col: usize) -> char {
+        self.grid[axial(self.height, row, col)].letter
+    }
+
+    fn is_answer(&self, row: usize, col: usize) -> bool {
+        let cell = axial(self.height, row, col);
+        self.placements.iter().any(|p| p.cells().contains(&cell))
+    }
+
+    /// Plain-text honeycomb for the terminal, odd rows indented by half a cell.
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.height {
+            if row % 2 == 1 {
+                out.push(' ');
+            }
+            for col in 0..self.width {
+                out.push(self.letter(row, col));
+                out.push(' ');
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Where each word starts on the printed honeycomb and which way it reads.
+    pub fn answer_key(&self) -> Vec<String> {
+        let offset = (self.height - 1) / 2;
+        self.placements
+            .iter()
+            .map(|p| format!("{}: row {}, column {}, reading {}", p.word, p.row + 1, p.col + p.row / 2 - offset + 1, compass(&p.dir)))
+            .collect()
+    }
+
+    fn render_honeycomb(&self, highlight: bool) -> String {
+        honeycomb(self.width, self.height, |row, col| {
+            let class = if highlight && self.is_answer(row, col) { "found" } else { "" };
+            (self.letter(row, col).to_string(), class.to_string())
+        })
+    }
+
+    /// A printable page in the style of `worksheets/hex.html`, followed by an answer key page.
+    pub fn render_html(&self, size: usize) -> String {
+        let mut words: Vec<&str> = self.placements.iter().map(|p| p.word.as_str()).collect();
+        words.sort();
+        let bank: String = words.iter().map(|w| format!("<li>{}</li>", w)).collect();
+        let key: String = self.answer_key().iter().map(|k| format!("<li>{}</li>", k)).collect();
+        HTML_TEMPLATE
+            .replace("{size}", &size.to_string())
+            .replace("{font}", &(size / 2).to_string())
+            .replace("{puzzle}", &self.render_honeycomb(false))
+            .replace("{answers}", &self.render_honeycomb(true))
+            .replace("{bank}", &bank)
+            .replace("{key}", &key)
+    }
+}
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Hexagonal Word Search</title>
+    <style>
+        @import url('https://fonts.googleapis.com/css2?family=Martian+Mono:wdth,wght@75..112.5,100..800&display=swap');
+
+        .martian-mono {
+            font-family: "Martian Mono", monospace;
+            font-optical-sizing: auto;
+            font-weight: 500;
+            font-style: normal;
+            font-variation-settings:
+                "width" 112.5;
+        }
+
+        #title {
+            font-size: 30px;
+            font-weight: bold;
+            width: 100%;
+            padding-bottom: 20px;
+        }
+
+        body {
+            background-color: dimgray;
+        }
+
+        .main {
+            --s: {size}px;
+            /* size  */
+            --m: 2px;
+            /* margin */
+            page-break-inside: avoid;
+            box-sizing: border-box;
+            width: 8.5in;
+            height: 11in;
+            padding: 15mm;
+            margin: 0 auto;
+            display: flex;
+            flex-flow: row wrap;
+            align-content: space-evenly;
+            justify-content: space-evenly;
+            page-break-after: always;
+            background: white;
+            overflow-y: hidden;
+        }
+
+        .hex-row {
+            font-size: 0;
+            /*disable white space between inline block element */
+            white-space: nowrap;
+        }
+
+        .hex-row.odd {
+            margin-left: calc(var(--s)/2 + var(--m));
+        }
+
+        .hex-row div {
+            width: var(--s);
+            margin: var(--m);
+            height: calc(var(--s)*1.1547);
+            display: inline-grid;
+            font-size: {font}px;
+            align-content: center;
+            justify-content: center;
+            clip-path: polygon(0% 25%, 0% 75%, 50% 100%, 100% 75%, 100% 25%, 50% 0%);
+            background: rgb(236, 236, 236);
+            margin-bottom: calc(var(--m) - var(--s)*0.2885);
+        }
+
+        .hex-row div.found {
+            background: rgb(255, 214, 102);
+        }
+
+        .words {
+            width: 100%;
+            columns: 4;
+            font-size: 18px;
+        }
+
+        @media print {
+            @page {
+                width: 8.5in;
+                height: 11in;
+                margin: 0mm;
+            }
+            body {
+                height: fit-content;
+                width: fit-content;
+                padding: 0;
+                margin: 0;
+                background-color: #fff;
+            }
+            .main {
+                margin: 0;
+            }
+        }
+    </style>
+</head>
+
+<body>
+    <div class="martian-mono main">
+        <div id="title">Find the words in the honeycomb!</div>
+{puzzle}        <ul class="words">{bank}</ul>
+    </div>
+    <div class="martian-mono main">
+        <div id="title">Answer key</div>
+{answers}        <ul class="words">{key}</ul>
+    </div>
+</body>
+
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blank_board() {
+        let board = blank_board(4, 5);
+        assert_eq!((board.rows(), board.cols()), (5, 6));
+        let on_board = board.iter().filter(|c| c.is_empty()).count();
+        assert_eq!(on_board, 20);
+        // Each row keeps its cells contiguous, shifting left every second row.
+        assert_eq!(axial(5, 0, 0), (0, 2));
+        assert_eq!(axial(5, 1, 0), (1, 2));
+        assert_eq!(axial(5, 2, 0), (2, 1));
+        assert_eq!(axial(5, 4, 3), (4, 3));
+    }
+
+    #[test]
+    fn test_step() {
+        let neighbors = |cell| {
+            let mut around: Vec<(usize, usize)> = HEX_DIRS.iter().filter_map(|dir| step(4, 5, cell, dir)).collect();
+            around.sort();
+            around
+        };
+        // Odd rows sit half a hex to the right, so they touch columns c and c+1 above and below.
+        assert_eq!(neighbors((1, 1)), vec![(0, 1), (0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]);
+        assert_eq!(neighbors((2, 1)), vec![(1, 0), (1, 1), (2, 0), (2, 2), (3, 0), (3, 1)]);
+        assert_eq!(neighbors((0, 0)).len(), 2);
+    }
+
+    #[test]
+    fn test_words_follow_hex_lines() {
+        let words: Vec<String> = ["HONEY", "BEE", "WAX", "HIVE", "QUEEN", "POLLEN"].iter().map(|w| w.to_string()).collect();
+        let search = HexSearch::generate(&words, 10, 10, &Blocklist::load(""));
+        assert!(search.missing.is_empty());
+        for p in &search.placements {
+            assert!(HEX_DIRS.contains(&p.dir));
+            for (cell, c) in p.cells().into_iter().zip(p.word.chars()) {
+                assert_eq!(search.grid[cell].letter, c);
+            }
+        }
+        assert!(search.grid.iter().all(|c| c.letter == OFF_BOARD || c.letter.is_ascii_uppercase()));
+        let text = search.render_text();
+        assert_eq!(text.lines().count(), 10);
+        assert!(text.lines().nth(1).unwrap().starts_with(' '));
+        let html = search.render_html(40);
+        assert_eq!(html.matches("class=\"found\"").count(), search.placements.iter().flat_map(|p| p.cells()).collect::<std::collections::HashSet<_>>().len());
+        assert!(html.contains("<li>HONEY</li>"));
+    }
+
+    #[test]
+    fn test_word_too_long() {
+        let words: Vec<String> = ["HONEYCOMBSANDMORE", "BEE", "WAX", "HIVE"].iter().map(|w| w.to_string()).collect();
+        let search = HexSearch::generate(&words, 12, 14, &Blocklist::load(""));
+        assert_eq!(search.missing, vec!["HONEYCOMBSANDMORE"]);
+        let mut placed: Vec<&str> = search.placements.iter().map(|p| p.word.as_str()).collect();
+        placed.sort();
+        assert_eq!(placed, vec!["BEE", "HIVE", "WAX"]);
+    }
+
+    #[test]
+    fn test_answer_key() {
+        let search = HexSearch {
+            width: 4,
+            height: 5,
+            grid: blank_board(4, 5),
+            placements: vec![Placement { word: "BEE".to_string(), row: 2, col: 1, dir: Direction::NE }],
+            missing: vec![],
+        };
+        assert_eq!(search.answer_key(), vec!["BEE: row 3, column 1, reading NE"]);
+    }
+}
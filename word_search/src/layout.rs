@@ -0,0 +1,140 @@
+/// Points to millimetres.
+pub const PT_TO_MM: f32 = 0.3528;
+/// Courier glyphs are 0.6em wide.
+pub const COURIER_WIDTH: f32 = 0.6;
+/// Room kept free at the top of each page for the title.
+const TITLE_SPACE: f32 = 14.0;
+/// Room under the title for the name and date lines.
+const NAME_SPACE: f32 = 10.0;
+/// Room at the bottom of each page for the page number.
+const FOOTER_SPACE: f32 = 8.0;
+/// Smallest gap between two problems, about the 20px margin in `grid.html`.
+const MIN_GAP: f32 = 5.0;
+
+/** Where things go on a printed page, in mm from its bottom-left corner.
+
+Every page has a header (the title, then name and date lines) and a footer with the page
+number. Problems share the body in between: `fit` says how many boxes of a given size the
+body holds, and `grid` spreads them out evenly, like the flexbox pages in `worksheets/`.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageSetup {
+    pub width: f32,
+    pub height: f32,
+    pub margin: f32,
+    /// Size of problem text in points; one problem cell is sized from it.
+    pub font_size: f32,
+}
+
+impl PageSetup {
+    /// US Letter with 15mm margins, the same 8.5in x 11in page the HTML worksheets print on.
+    pub fn letter(font_size: f32) -> PageSetup {
+        PageSetup { width: 215.9, height: 279.4, margin: 15.0, font_size }
+    }
+
+    /// Height of one digit cell, leaving a little room between rows like `--vsize`.
+    pub fn cell_height(&self) -> f32 {
+        self.font_size * PT_TO_MM * 1.25
+    }
+
+    /// Width of one digit cell; `grid.html` uses 0.6 of the height.
+    pub fn cell_width(&self) -> f32 {
+        self.cell_height() * 0.6
+    }
+
+    /// Baseline of the title.
+    pub fn title_y(&self, title_size: f32) -> f32 {
+        self.height - self.margin - title_size * PT_TO_MM
+    }
+
+    /// Baseline of the name and date lines.
+    pub fn name_y(&self) -> f32 {
+        self.height - self.margin - TITLE_SPACE - NAME_SPACE / 2.0
+    }
+
+    /// Baseline of the page number.
+    pub fn footer_y(&self) -> f32 {
+        self.margin
+    }
+
+    /// Top and bottom of the area left for problems.
+    pub fn body(&self) -> (f32, f32) {
+        (self.height - self.margin - TITLE_SPACE - NAME_SPACE, self.margin + FOOTER_SPACE)
+    }
+
+    /// Width of `text` set in Courier at `size` points.
+    pub fn text_width(text: &str, size: f32) -> f32 {
+        text.chars().count() as f32 * size * PT_TO_MM * COURIER_WIDTH
+    }
+
+    /// How many `rows` x `cols` problems fit on a page, as (across, down).
+    pub fn fit(&self, rows: usize, cols: usize) -> (usize, usize) {
+        let (top, bottom) = self.body();
+        let box_w = cols as f32 * self.cell_width();
+        let box_h = rows as f32 * self.cell_height();
+        let across = ((self.width - 2.0 * self.margin + MIN_GAP) / (box_w + MIN_GAP)).floor() as usize;
+        let down = ((top - bottom + MIN_GAP) / (box_h + MIN_GAP)).floor() as usize;
+        (across, down)
+    }
+
+    /** Top-left corners for `count` problems of `rows` x `cols` cells, in reading order.
+
+    Boxes go on the grid from `fit`, with the leftover space shared evenly around them like
+    `justify-content: space-evenly`. Rows that aren't needed don't take up space, so a
+    short page spreads its problems over the whole body. Anything past one page is dropped.
+    */
+    pub fn grid(&self, count: usize, rows: usize, cols: usize) -> Vec<(f32, f32)> {
+        let (across, down) = self.fit(rows, cols);
+        if across == 0 || down == 0 {
+            return vec![];
+        }
+        let (top, bottom) = self.body();
+        let box_w = cols as f32 * self.cell_width();
+        let box_h = rows as f32 * self.cell_height();
+        let used_down = count.div_ceil(across).min(down);
+        let gap_x = (self.width - 2.0 * self.margin - across as f32 * box_w) / (across + 1) as f32;
+        let gap_y = (top - bottom - used_down as f32 * box_h) / (used_down + 1) as f32;
+        (0..count.min(across * down))
+            .map(|i| {
+                let (r, c) = (i / across, i % across);
+                (self.margin + gap_x + c as f32 * (box_w + gap_x), top - gap_y - r as f32 * (box_h + gap_y))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        let setup = PageSetup::letter(17.0);
+        // 4 rows of 3 cells at 17pt: roughly 13.5mm x 30mm per box.
+        assert_eq!(setup.fit(4, 3), (10, 6));
+        assert_eq!(setup.fit(4, 7), (5, 6));
+        // A bigger font fits fewer.
+        assert_eq!(PageSetup::letter(34.0).fit(4, 3), (5, 3));
+    }
+
+    #[test]
+    fn test_grid() {
+        let setup = PageSetup::letter(17.0);
+        let (top, bottom) = setup.body();
+        let slots = setup.grid(100, 4, 3);
+        assert_eq!(slots.len(), 60);
+        let box_h = 4.0 * setup.cell_height();
+        for (x, y) in &slots {
+            assert!(*x >= setup.margin && *x + 3.0 * setup.cell_width() <= setup.width - setup.margin);
+            assert!(*y <= top && *y - box_h >= bottom);
+        }
+        // Even spacing: the same step between every column and every row.
+        let step_x = slots[1].0 - slots[0].0;
+        assert!((slots[9].0 - slots[8].0 - step_x).abs() < 1e-3);
+        let step_y = slots[0].1 - slots[10].1;
+        assert!((slots[40].1 - slots[50].1 - step_y).abs() < 1e-3);
+        // A single row of problems sits in the middle of the body.
+        let one = setup.grid(3, 4, 3);
+        assert!((top - one[0].1 - (one[0].1 - box_h - bottom)).abs() < 1e-3);
+    }
+}
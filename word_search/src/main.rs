@@ -1,4 +1,3 @@
-use std::fmt::Result;
 use std::io::{self,  BufRead};
 use rand::seq::SliceRandom;
 use rand::Rng;
@@ -7,6 +6,31 @@ use grid::*;
 use std::assert_eq;
 use bitflags::bitflags;
 
+mod arithmetic;
+mod constraints;
+mod crossword;
+mod division;
+mod facts;
+mod fillin;
+mod filter;
+mod hex;
+mod layout;
+mod multiplication;
+mod packet;
+mod pairs;
+mod pdf;
+mod play;
+
+use arithmetic::{OperandRange, Operator, Problem, Regrouping};
+use constraints::Constraints;
+use crossword::Crossword;
+use fillin::FillIn;
+use filter::Blocklist;
+use hex::HexSearch;
+use layout::PageSetup;
+use pairs::{PairPuzzle, Rule};
+use pdf::Sheet;
+
 
 
 
@@ -66,8 +90,30 @@ fn map(m: char) -> u8 {
 }
 
 const GRID_SIZE: usize = 48;
+const PLAY_GRID_SIZE: usize = 15;
+const CROSSWORD_SIZE: usize = 21;
+const HEX_WIDTH: usize = 12;
+const HEX_HEIGHT: usize = 14;
+const HEX_CELL_PX: usize = 40;
+const PAIRS_WIDTH: usize = 9;
+const PAIRS_HEIGHT: usize = 11;
+const PAIRS_CELL_PX: usize = 60;
 const EMPTY: char = '.';
+/// Marks cells outside the playing area, like the corners a honeycomb leaves in its axial grid.
+const OFF_BOARD: char = '#';
 const VALID_DIRS: [Direction; 4] = [Direction::EE, Direction::SS, Direction::SE, Direction::NE];
+const ALL_DIRS: [Direction; 8] = [
+    Direction::EE, Direction::NE, Direction::NN, Direction::NW,
+    Direction::WW, Direction::SW, Direction::SS, Direction::SE,
+];
+/// Directions that follow a line of hexes when the grid holds axial coordinates.
+const HEX_DIRS: [Direction; 6] = [
+    Direction::EE, Direction::WW, Direction::NN,
+    Direction::SS, Direction::NE, Direction::SW,
+];
+/// Worksheet digits print at this size in points, like `createProblems(..., 17)` in `grid.html`.
+const WORKSHEET_FONT_SIZE: f32 = 17.0;
+const ALL_WORDS: &str = include_str!("all_words.txt");
 
 
 bitflags! {
@@ -107,6 +153,9 @@ impl Character {
     fn is_empty(&self) -> bool {
         return self.letter == EMPTY;
     }
+    fn is_off_board(&self) -> bool {
+        self.letter == OFF_BOARD
+    }
     fn combine_with(&mut self, other: &Character) {
         if self.is_empty() {
             self.letter = other.letter;
@@ -156,6 +205,7 @@ fn ggd(char_grid: Grid<char>, direction: CharDirection) -> Grid<Character> {
 struct Board {
     grid: Grid<Character>,
     dir: Orientation,
+    placement: Option<Placement>,
 }
 
 
@@ -165,6 +215,8 @@ struct Board {
 
 If the word can fit in the grid at a certain position, the corresponding position in the grid gets a score >= 1.
 If the word can't fit in the grid at the specified position, the position is scored 0.
+Off-board cells never take a letter, so the same scoring works on masked shapes such as
+a honeycomb stored in axial coordinates.
 
 TODO: implement letter-frequency into scoring, like scrabble.
 */ 
@@ -184,7 +236,9 @@ fn convolve(grid: &Grid<Character>, word: &Grid<Character>) -> Grid<f32> {
                 continue;
             }
             if let Some(value) = grid.get(r+row,c+col) {
-                if value.letter == EMPTY {
+                if value.is_off_board() {
+                    *score = 0.0;
+                } else if value.letter == EMPTY {
                     *score = *score * 1.0;
                 } else if value.letter == letter.letter && !value.directions.intersects(letter.directions) {
                     // TODO: add letter frequency scor,ing
@@ -201,16 +255,45 @@ fn convolve(grid: &Grid<Character>, word: &Grid<Character>) -> Grid<f32> {
 
 fn main() {
     println!("Hello, world!");
-    let initial_grid = Grid::init(GRID_SIZE, GRID_SIZE, Character::default()); // Initial empty grid
-    let mut grid_stack = vec![Board{grid: initial_grid, dir: Orientation::None}]; // Stack of grids starts with the initial grid
-    let mut words = read_and_clean_words();
+    let blocklist = Blocklist::default();
+    let mut args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|s| s.as_str()) {
+        Some("crossword") => return print_crossword(&read_clues(&blocklist)),
+        Some("fillin") => return print_fill_in(&read_and_clean_words(&blocklist)),
+        Some("pairs") => return print_pair_puzzle(&args[2..]),
+        Some("packet") => return save_packet(&args[2..], &blocklist),
+        Some("hex") => return print_hex_search(&read_and_clean_words(&blocklist), &blocklist, args.get(2)),
+        _ => {}
+    }
+    let (sheet_args, path) = split_path(&args[1..], ".pdf");
+    if let Some(sheet) = worksheet(sheet_args, &blocklist) {
+        return save_sheet(sheet, path.unwrap_or("worksheet.pdf"));
+    }
+    let play = args.get(1).map(|s| s.as_str()) == Some("play");
+    if play {
+        args.remove(1);
+    }
+    let size = if play { PLAY_GRID_SIZE } else { GRID_SIZE };
+    let words = match args.get(1).map(|s| s.as_str()) {
+        Some("random") => {
+            let count = args.get(2).and_then(|n| n.parse().ok()).unwrap_or(20);
+            random_words(ALL_WORDS, count, &blocklist)
+        }
+        _ => read_and_clean_words(&blocklist),
+    };
     let table = Words::load(words.clone());
-    words.sort_by(|a, b| b.len().cmp(&a.len()));
-    let words = words;
-    place_words_backtrack_convolution(&mut grid_stack, &words, 0);
-    let mut g = grid_stack.last().unwrap().grid.clone();
-    //replace_dots_with_random_letters(&mut g);
-    println!("\ngrid size: {}", grid_stack.len());
+    let (g, placements, missing) = word_search(&words, size, &blocklist);
+    for word in &missing {
+        println!("couldn't fit: {}", word);
+    }
+    if play && placements.is_empty() {
+        return println!("none of the words fit on the grid");
+    }
+    if play {
+        play::run(g, placements).expect("Terminal error");
+        return;
+    }
+    println!("\ngrid size: {}", placements.len() + 1);
     print_grid(&g);
                    
 }
@@ -225,6 +308,7 @@ enum Orientation {
 
 #[derive(PartialEq)]
 #[derive(Clone)]
+#[derive(Debug)]
 enum Direction {
     EE,
     NE,
@@ -236,6 +320,56 @@ enum Direction {
     SE,
 }
 
+impl Direction {
+    /// Row and column increments for one step in this direction.
+    fn step(&self) -> (isize, isize) {
+        use Direction::*;
+        match self {
+            EE => ( 0,  1),
+            NE => (-1,  1),
+            NN => (-1,  0),
+            NW => (-1, -1),
+            WW => ( 0, -1),
+            SW => ( 1, -1),
+            SS => ( 1,  0),
+            SE => ( 1,  1),
+        }
+    }
+}
+
+/// Where a word ended up: the cell holding its first letter and the direction it reads.
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+struct Placement {
+    word: String,
+    row: usize,
+    col: usize,
+    dir: Direction,
+}
+
+impl Placement {
+    /// Builds a placement from the top-left offset that `convolve` scores and `combine` takes.
+    fn from_offset(word: &str, dir: &Direction, row: usize, col: usize) -> Placement {
+        let (ri, ci) = dir.step();
+        let last = word.len() - 1;
+        Placement {
+            word: word.to_string(),
+            row: if ri < 0 { row + last } else { row },
+            col: if ci < 0 { col + last } else { col },
+            dir: dir.clone(),
+        }
+    }
+
+    /// Grid cells covered by the word, in reading order.
+    fn cells(&self) -> Vec<(usize, usize)> {
+        let (ri, ci) = self.dir.step();
+        (0..self.word.len() as isize)
+            .map(|i| ((self.row as isize + i * ri) as usize, (self.col as isize + i * ci) as usize))
+            .collect()
+    }
+}
+
 #[derive(PartialEq)]
 #[derive(Clone)]
 struct Candidate {
@@ -247,6 +381,8 @@ struct Candidate {
     max_placement_value: f32,
 }
 
+/// Lays a word out in its own small grid. Works for square grids and axial hex grids alike,
+/// since the hex directions are a subset of the square ones (see `HEX_DIRS`).
 fn to_grid(word: &String, dir: Direction) -> Grid<Character> {
     use Direction::*;
     let cols = match dir {
@@ -316,7 +452,7 @@ fn combine(grid: &mut Grid<Character>, word: &Grid<Character>, row: usize, colum
 
 
 
-fn place_words_backtrack_convolution(grid_stack: &mut Vec<Board>, words: &Vec<String>, index: usize) -> bool {
+fn place_words_backtrack_convolution(grid_stack: &mut Vec<Board>, words: &Vec<String>, index: usize, valid_dirs: &[Direction]) -> bool {
     if index == words.len() {
         return true; // All words placed
     }
@@ -324,7 +460,7 @@ fn place_words_backtrack_convolution(grid_stack: &mut Vec<Board>, words: &Vec<St
     let word = &words[index];
     let last = grid_stack.last().unwrap().clone();
     
-    let mut candidates = Candidate::create(&last.grid, word, &VALID_DIRS);
+    let mut candidates = Candidate::create(&last.grid, word, valid_dirs);
     if candidates.is_empty() {
         return false;
     }
@@ -333,8 +469,9 @@ fn place_words_backtrack_convolution(grid_stack: &mut Vec<Board>, words: &Vec<St
         for placement in candidate.max_placements {
             let mut current_grid  = last.grid.clone(); 
             combine(&mut current_grid, &candidate.as_grid, placement.0, placement.1);
-            grid_stack.push(Board { grid: current_grid.clone(), dir: Orientation::None });
-            if place_words_backtrack_convolution(grid_stack, words, index+1) {
+            let placed = Placement::from_offset(word, &candidate.dir, placement.0, placement.1);
+            grid_stack.push(Board { grid: current_grid.clone(), dir: Orientation::None, placement: Some(placed) });
+            if place_words_backtrack_convolution(grid_stack, words, index+1, valid_dirs) {
                 return true;
             }
             grid_stack.pop();
@@ -415,7 +552,7 @@ fn place_words_backtrack(grid_stack: &mut Vec<Board>, words: &Vec<String>, index
                         },
                         Orientation::None => {},
                     }
-                    grid_stack.push(Board{grid: grid_attempt, dir: orientation.clone()}); // Push the successful attempt onto the stack
+                    grid_stack.push(Board{grid: grid_attempt, dir: orientation.clone(), placement: None}); // Push the successful attempt onto the stack
                     if place_words_backtrack(grid_stack, words, index + 1) {
                         return true; // Successfully placed all words
                     } else {
@@ -437,7 +574,424 @@ fn print_grid<T: std::fmt::Display + Copy>(grid: &Grid<T>) {
     }
 }
 
-fn read_and_clean_words() -> Vec<String> {
+/** Places the words, longest first, on a `size` x `size` grid and fills the gaps with letters.
+
+Words that don't fit are left out and returned last, like `HexSearch::missing`.
+*/
+fn word_search(words: &[String], size: usize, blocklist: &Blocklist) -> (Grid<Character>, Vec<Placement>, Vec<String>) {
+    let (mut words, too_long): (Vec<String>, Vec<String>) = words.iter().cloned().partition(|w| w.len() <= size);
+    words.sort_by_key(|w| std::cmp::Reverse(w.len()));
+    let initial_grid = Grid::init(size, size, Character::default()); // Initial empty grid
+    let mut grid_stack = vec![Board{grid: initial_grid, dir: Orientation::None, placement: None}]; // Stack of grids starts with the initial grid
+    // False means not even the first word fit. Otherwise what made it onto the stack is what
+    // counts, since a dead end part way down still comes back true with the words before it.
+    let placed_any = place_words_backtrack_convolution(&mut grid_stack, &words, 0, &VALID_DIRS);
+    let placements: Vec<Placement> = grid_stack.iter().filter_map(|b| b.placement.clone()).collect();
+    let mut missing = too_long;
+    missing.extend(words.into_iter().filter(|w| !placed_any || !placements.iter().any(|p| &p.word == w)));
+    let mut g = grid_stack.last().unwrap().grid.clone();
+    if !filter::fill_with_random_letters(&mut g, blocklist, &mut rand::thread_rng()) {
+        println!("warning: couldn't re-roll filler clear of blocked words");
+    }
+    (g, placements, missing)
+}
+
+/// Picks `count` distinct random words from a newline-separated list, skipping blocked words.
+fn random_words(list: &str, count: usize, blocklist: &Blocklist) -> Vec<String> {
+    let pool: Vec<&str> = list
+        .lines()
+        .filter(|w| w.len() >= 3 && w.len() <= GRID_SIZE)
+        .filter(|w| w.chars().all(|c| c.is_ascii_alphabetic()))
+        .filter(|w| blocklist.allows(w))
+        .collect();
+    pool.choose_multiple(&mut rand::thread_rng(), count)
+        .map(|w| w.to_ascii_uppercase())
+        .collect()
+}
+
+fn print_crossword(clues: &[(String, String)]) {
+    match Crossword::generate(clues, CROSSWORD_SIZE) {
+        Some(crossword) => {
+            print!("{}", crossword.render_blank());
+            println!();
+            print!("{}", crossword.render_clues());
+            println!("\nSOLUTION");
+            print_grid(&crossword.grid);
+        }
+        None => println!("Couldn't fit every word into one connected crossword. Try fewer or shorter words."),
+    }
+}
+
+fn print_fill_in(words: &[String]) {
+    match FillIn::generate(words, CROSSWORD_SIZE) {
+        Some(fill_in) => {
+            print!("{}", fill_in.render_skeleton());
+            println!();
+            print!("{}", fill_in.render_word_bank());
+            println!("\nSOLUTION");
+            print_grid(&fill_in.grid);
+        }
+        None => println!("Couldn't find a connected layout with only one solution. Try different words."),
+    }
+}
+
+/// Prints a hex word search, and writes the honeycomb page to `html_path` if one is given.
+fn print_hex_search(words: &[String], blocklist: &Blocklist, html_path: Option<&String>) {
+    let search = HexSearch::generate(words, HEX_WIDTH, HEX_HEIGHT, blocklist);
+    print!("{}", search.render_text());
+    for word in &search.missing {
+        println!("couldn't fit: {}", word);
+    }
+    println!();
+    for line in search.answer_key() {
+        println!("{}", line);
+    }
+    if let Some(path) = html_path {
+        std::fs::write(path, search.render_html(HEX_CELL_PX)).expect("Failed to write HTML");
+        println!("\nwrote {}", path);
+    }
+}
+
+/// Splits a trailing output path ending in `extension` off the arguments.
+fn split_path<'a>(args: &'a [String], extension: &str) -> (&'a [String], Option<&'a str>) {
+    match args.split_last() {
+        Some((last, rest)) if last.ends_with(extension) => (rest, Some(last.as_str())),
+        _ => (args, None),
+    }
+}
+
+/** Builds the printable worksheet named by `args[0]` from the rest of `args`.
+
+Used both for standalone PDFs and for the sheets of a packet. Returns `None` if `args[0]`
+isn't a worksheet.
+*/
+fn worksheet(args: &[String], blocklist: &Blocklist) -> Option<Result<Sheet, String>> {
+    let (name, rest) = args.split_first()?;
+    Some(match name.as_str() {
+        "addition" => arithmetic_sheet(rest, Operator::Add, false),
+        "addition-ordered" => arithmetic_sheet(rest, Operator::Add, true),
+        "subtraction" => arithmetic_sheet(rest, Operator::Subtract, false),
+        "subtraction-ordered" => arithmetic_sheet(rest, Operator::Subtract, true),
+        "multiplication" => multiplication_sheet(rest),
+        "division" => division_sheet(rest),
+        "facts" => fact_sheet(rest),
+        "wordsearch" => word_search_sheet(rest, blocklist),
+        "pairs" => pair_puzzle(rest).map(|puzzle| puzzle.sheet()),
+        _ => return None,
+    })
+}
+
+/// Writes a worksheet and its answer key to `path`, or says why there isn't one.
+fn save_sheet(sheet: Result<Sheet, String>, path: &str) {
+    match sheet {
+        Ok(sheet) => {
+            let title = sheet.title.clone();
+            sheet.save(&PageSetup::letter(WORKSHEET_FONT_SIZE), std::path::Path::new(path)).expect("Failed to write PDF");
+            println!("wrote {} to {}", title, path);
+        }
+        Err(e) => println!("{}", e),
+    }
+}
+
+/** Writes a packet of worksheets to one PDF: `packet SPEC [out.pdf]`.
+
+The spec file lists one worksheet per line as it would be written on the command line,
+e.g. `addition 10 99 10 99 some` or `pairs 10 rule=product`; see `packet::read_spec`.
+*/
+fn save_packet(args: &[String], blocklist: &Blocklist) {
+    let (args, path) = split_path(args, ".pdf");
+    let path = path.unwrap_or("packet.pdf");
+    let Some(spec) = args.first() else {
+        return println!("usage: packet SPEC [out.pdf]");
+    };
+    let spec = match std::fs::read_to_string(spec) {
+        Ok(spec) => spec,
+        Err(e) => return println!("couldn't read {}: {}", spec, e),
+    };
+    let (title, lines) = packet::read_spec(&spec);
+    let mut sheets = vec![];
+    for line in &lines {
+        match worksheet(line, blocklist) {
+            Some(Ok(sheet)) => sheets.push(sheet),
+            Some(Err(e)) => return println!("{}: {}", line.join(" "), e),
+            None => return println!("{}: not a worksheet", line.join(" ")),
+        }
+    }
+    let pages = packet::assemble(&title, sheets);
+    pdf::save_pdf(&title, &pages, &PageSetup::letter(WORKSHEET_FONT_SIZE), std::path::Path::new(path)).expect("Failed to write PDF");
+    println!("wrote {} worksheets on {} pages to {}", lines.len(), pages.len(), path);
+}
+
+/** Reads a number honeycomb's settings and generates it.
+
+Takes `TARGET [SMALLEST LARGEST] [rule=sum|product|difference|triple] [pairs=N]
+[accidental=N]`. Numbers run from 1 to 9 by default, with 8 planted groups and no
+accidental ones; `accidental=N` allows up to N more groups that happen to obey the rule.
+*/
+fn pair_puzzle(args: &[String]) -> Result<PairPuzzle, String> {
+    let numbers: Vec<i64> = args.iter().map_while(|a| a.parse().ok()).collect();
+    let (target, values) = match numbers.as_slice() {
+        [target] => (*target, OperandRange::new(1, 9)),
+        [target, smallest, largest] => (*target, OperandRange::new(*smallest, *largest)),
+        _ => return Err(String::from("usage: pairs TARGET [SMALLEST LARGEST] [rule=sum|product|difference|triple] [pairs=N] [accidental=N] [out.html]")),
+    };
+    let (mut rule, mut planted, mut accidental) = (Rule::Sum, 8, 0);
+    for arg in &args[numbers.len()..] {
+        match arg.split_once('=') {
+            Some(("rule", "sum")) => rule = Rule::Sum,
+            Some(("rule", "product")) => rule = Rule::Product,
+            Some(("rule", "difference")) => rule = Rule::Difference,
+            Some(("rule", "triple")) => rule = Rule::Triple,
+            Some(("pairs", n)) => planted = n.parse().map_err(|_| format!("bad count {}", arg))?,
+            Some(("accidental", n)) => accidental = n.parse().map_err(|_| format!("bad count {}", arg))?,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    PairPuzzle::generate(rule, target, PAIRS_WIDTH, PAIRS_HEIGHT, values, planted, accidental, &mut rand::thread_rng())
+}
+
+/// Prints a number honeycomb and its answer key, and writes both as HTML (`pairs.html` unless given).
+fn print_pair_puzzle(args: &[String]) {
+    let (args, path) = split_path(args, ".html");
+    let puzzle = match pair_puzzle(args) {
+        Ok(puzzle) => puzzle,
+        Err(e) => return println!("{}", e),
+    };
+    print!("{}", puzzle.render_text());
+    println!("\n{} planted, {} accidental", puzzle.planted.len(), puzzle.accidental().len());
+    for line in puzzle.answer_key() {
+        println!("{}", line);
+    }
+    let path = path.unwrap_or("pairs.html");
+    std::fs::write(path, puzzle.render_html(PAIRS_CELL_PX)).expect("Failed to write HTML");
+    println!("\nwrote {}", path);
+}
+
+/// A `wordsearch [COUNT]` page of random words on a play-sized grid, with a word bank and a key.
+fn word_search_sheet(args: &[String], blocklist: &Blocklist) -> Result<Sheet, String> {
+    let count = match args.first() {
+        Some(n) => n.parse().map_err(|_| String::from("usage: wordsearch [COUNT] [out.pdf]"))?,
+        None => 15,
+    };
+    let words = random_words(ALL_WORDS, count, blocklist);
+    let (grid, placements, missing) = word_search(&words, PLAY_GRID_SIZE, blocklist);
+    if placements.is_empty() {
+        return Err(String::from("none of the words fit on the grid"));
+    }
+    let letters = Grid::from_vec(grid.iter().map(|c| c.letter).collect(), grid.cols());
+    let mut bank: Vec<String> = placements.iter().map(|p| p.word.clone()).collect();
+    bank.sort();
+    let lines = placements
+        .iter()
+        .map(|p| {
+            let cells = p.cells();
+            (cells[0], cells[cells.len() - 1])
+        })
+        .collect();
+    let title = String::from("Word search");
+    Ok(Sheet {
+        pages: vec![pdf::Page { notes: bank, name_line: true, ..pdf::Page::new(title.clone(), pdf::Body::Letters { grid: letters.clone(), lines: vec![] }) }],
+        key: vec![pdf::Page {
+            notes: missing.iter().map(|w| format!("Left out, didn't fit: {}", w)).collect(),
+            ..pdf::Page::new(format!("{} - answer key", title), pdf::Body::Letters { grid: letters, lines })
+        }],
+        title,
+    })
+}
+
+/** Column-arithmetic problems plus an answer key.
+
+Random mode takes `min1 max1 min2 max2 [regrouping]`, ordered mode takes `min max
+[regrouping]` and skips the pairs that break any of the rules below.
+Regrouping is one of `any`, `none`, `some`, or the one column that must regroup:
+`ones`, `tens`, `hundreds`, `thousands`. After that either mode also takes any of the
+constraints understood by `Constraints::parse_arg`, such as `carries=2` or
+`result=0..20`. Subtraction never goes negative unless `result` says otherwise.
+The page is filled with as many problems as fit at the largest operand size.
+*/
+fn arithmetic_sheet(args: &[String], operator: Operator, ordered: bool) -> Result<Sheet, String> {
+    let numbers: Vec<i64> = args.iter().map_while(|a| a.parse().ok()).collect();
+    let mut rest = args[numbers.len()..].iter().map(|s| s.as_str()).peekable();
+    let regrouping = match rest.peek().copied() {
+        Some("any") => Regrouping::Any,
+        Some("none") => Regrouping::None,
+        Some("some") => Regrouping::AtLeastOne,
+        Some("ones") => Regrouping::Column(0),
+        Some("tens") => Regrouping::Column(1),
+        Some("hundreds") => Regrouping::Column(2),
+        Some("thousands") => Regrouping::Column(3),
+        _ => Regrouping::Any,
+    };
+    if regrouping != Regrouping::Any || rest.peek() == Some(&"any") {
+        rest.next();
+    }
+    let mut constraints = Constraints { regrouping, ..Constraints::default() };
+    let mut constrained = false;
+    for arg in rest {
+        if !constraints.parse_arg(arg)? {
+            return Err(format!("unknown option {}", arg));
+        }
+        constrained = true;
+    }
+    let (first, second) = match (ordered, numbers.as_slice()) {
+        (true, [min, max]) => (OperandRange::new(*min, *max), OperandRange::new(*min, *max)),
+        (false, [min1, max1, min2, max2]) => (OperandRange::new(*min1, *max1), OperandRange::new(*min2, *max2)),
+        _ => return Err(format!("usage: {0} MIN1 MAX1 MIN2 MAX2 [REGROUPING] [out.pdf] | {0}-ordered MIN MAX [REGROUPING] [out.pdf]", operator.name())),
+    };
+    // The widest operand may be a negative minimum.
+    let widest_of = |range: OperandRange| if range.min.to_string().len() > range.max.to_string().len() { range.min } else { range.max };
+    let widest = Problem::new(operator, widest_of(first), widest_of(second)).layout(true);
+    let (across, down) = PageSetup::letter(WORKSHEET_FONT_SIZE).fit(widest.cells.rows(), widest.cells.cols());
+    let count = across * down;
+    let non_negative = operator == Operator::Subtract;
+    if non_negative && constraints.result.is_none() {
+        constraints.result = Some(OperandRange::new(0, i64::MAX));
+    }
+    let problems = if ordered {
+        // The regrouping and the no-negatives rule are part of `constraints` too.
+        let problems = arithmetic::ordered_problems(operator, first, count, |p| constraints.allows(p));
+        if problems.is_empty() {
+            return Err(format!("no {} problem in this range satisfies the constraints", operator.name()));
+        }
+        problems
+    } else if constrained {
+        constraints::constrained_problems(operator, first, second, &constraints, count, &mut rand::thread_rng())?
+    } else if regrouping == Regrouping::Any && !non_negative {
+        arithmetic::random_problems(operator, first, second, count, &mut rand::thread_rng())
+    } else {
+        arithmetic::regrouping_problems(operator, first, second, regrouping, non_negative, count, &mut rand::thread_rng())?
+    };
+    Ok(problem_sheet(operator, &problems))
+}
+
+/// `multiplication DIGITS1 DIGITS2`: operands with exactly that many digits.
+fn multiplication_sheet(args: &[String]) -> Result<Sheet, String> {
+    let digits: Vec<u32> = args.iter().map_while(|a| a.parse().ok()).collect();
+    let (first, second) = match digits.as_slice() {
+        [d1, d2] => (OperandRange::digits(*d1), OperandRange::digits(*d2)),
+        _ => return Err(String::from("usage: multiplication DIGITS1 DIGITS2 [out.pdf]")),
+    };
+    let widest = Problem::new(Operator::Multiply, first.max, second.max).layout(true);
+    let (across, down) = PageSetup::letter(WORKSHEET_FONT_SIZE).fit(widest.cells.rows(), widest.cells.cols());
+    let problems = arithmetic::random_problems(Operator::Multiply, first, second, across * down, &mut rand::thread_rng());
+    Ok(problem_sheet(Operator::Multiply, &problems))
+}
+
+/// `division DIVIDEND_DIGITS DIVISOR_DIGITS [exact|remainders]`; problems divide exactly by default.
+fn division_sheet(args: &[String]) -> Result<Sheet, String> {
+    let usage = || String::from("usage: division DIVIDEND_DIGITS DIVISOR_DIGITS [exact|remainders] [out.pdf]");
+    let digits: Vec<u32> = args.iter().map_while(|a| a.parse().ok()).collect();
+    let remainders = match args[digits.len()..].first().map(|s| s.as_str()) {
+        None | Some("exact") => false,
+        Some("remainders") => true,
+        Some(_) => return Err(usage()),
+    };
+    let (dividend, divisor) = match digits.as_slice() {
+        [d1, d2] => (*d1, *d2),
+        _ => return Err(usage()),
+    };
+    let (rows, cols) = division::size(dividend.max(1) as usize, divisor.max(1) as usize);
+    let (across, down) = PageSetup::letter(WORKSHEET_FONT_SIZE).fit(rows, cols);
+    let problems = division::division_problems(OperandRange::digits(dividend), OperandRange::digits(divisor), remainders, across * down, &mut rand::thread_rng())?;
+    Ok(problem_sheet(Operator::Divide, &problems))
+}
+
+/** A numbered fact drill plus an answer key.
+
+Takes `OPERATOR FAMILY [OTHERS] [pages=N]`, where the operator is `add`, `subtract`,
+`multiply` or `divide` and the ranges are `N` or `MIN..MAX`, e.g. `facts multiply 6`
+or `facts add 0..10`. Others default to 0..10. Without `pages` there are just enough
+pages to show every fact once.
+*/
+fn fact_sheet(args: &[String]) -> Result<Sheet, String> {
+    let usage = || String::from("usage: facts add|subtract|multiply|divide FAMILY [OTHERS] [pages=N] [out.pdf]");
+    let operator = match args.first().map(|s| s.as_str()) {
+        Some("add") => Operator::Add,
+        Some("subtract") => Operator::Subtract,
+        Some("multiply") => Operator::Multiply,
+        Some("divide") => Operator::Divide,
+        _ => return Err(usage()),
+    };
+    let parse_range = |arg: Option<&String>| -> Option<OperandRange> {
+        let arg = arg?;
+        let (min, max) = arg.split_once("..").unwrap_or((arg, arg));
+        Some(OperandRange::new(min.parse().ok()?, max.parse().ok()?))
+    };
+    let family = parse_range(args.get(1)).ok_or_else(usage)?;
+    let mut rest = &args[2..];
+    let others = match parse_range(rest.first()) {
+        Some(others) => {
+            rest = &rest[1..];
+            others
+        }
+        None => OperandRange::new(0, 10),
+    };
+    let pages = match rest.first() {
+        Some(arg) => Some(arg.strip_prefix("pages=").and_then(|n| n.parse::<usize>().ok()).ok_or_else(usage)?),
+        None => None,
+    };
+    let set = facts::FactSet { operator, family, others };
+    let fact_count = set.facts().len();
+    if fact_count == 0 {
+        return Err(format!("no {} facts in those ranges", operator.name()));
+    }
+    // Size the page for the widest problem any fact could make.
+    let sample = facts::fact_problems(&set, fact_count * 2, &mut rand::thread_rng());
+    let (across, down) = PageSetup::letter(WORKSHEET_FONT_SIZE).fit(1, facts::FactWidths::of(&sample).cols() + 1);
+    let per_page = across * down;
+    let pages = pages.unwrap_or(fact_count.div_ceil(per_page)).max(1);
+    let problems = facts::fact_problems(&set, per_page * pages, &mut rand::thread_rng());
+    let widths = facts::FactWidths::of(&problems);
+    let family_name = if family.min == family.max { family.min.to_string() } else { format!("{}..{}", family.min, family.max) };
+    let title = format!("{}{} facts: {}{}", operator.name()[..1].to_uppercase(), &operator.name()[1..], operator.symbol(), family_name);
+    let page = |key: bool, (n, chunk): (usize, &[Problem])| {
+        let layouts = chunk.iter().enumerate().map(|(i, p)| facts::layout(n * per_page + i + 1, p, widths, key)).collect();
+        let title = if key { format!("{} - answer key", title) } else { title.clone() };
+        pdf::Page { name_line: !key, ..pdf::Page::new(title, pdf::Body::Problems(layouts)) }
+    };
+    Ok(Sheet {
+        pages: problems.chunks(per_page).enumerate().map(|c| page(false, c)).collect(),
+        key: problems.chunks(per_page).enumerate().map(|c| page(true, c)).collect(),
+        title,
+    })
+}
+
+/// The problems on one page and their worked answers on a second.
+fn problem_sheet(operator: Operator, problems: &[Problem]) -> Sheet {
+    let title = operator.name()[..1].to_uppercase() + &operator.name()[1..];
+    let page = |title: String, key: bool| pdf::Page::new(title, pdf::Body::Problems(problems.iter().map(|p| p.layout(key)).collect()));
+    Sheet {
+        pages: vec![pdf::Page { name_line: true, ..page(title.clone(), false) }],
+        key: vec![page(format!("{} - answer key", title), true)],
+        title,
+    }
+}
+
+/// Reads `answer: clue` lines for crosswords. A line without a colon gets an empty clue.
+fn read_clues(blocklist: &Blocklist) -> Vec<(String, String)> {
+    let stdin = io::stdin();
+    let mut clues: Vec<(String, String)> = Vec::new();
+
+    println!("Enter answer: clue (Press ENTER on a blank line to finish):");
+
+    for line in stdin.lock().lines() {
+        let line = line.expect("Failed to read line");
+        if line.is_empty() {
+            break;
+        }
+        let (answer, clue) = line.split_once(':').unwrap_or((&line, ""));
+        let answer = answer.replace(" ", "").to_ascii_uppercase();
+        if !blocklist.allows(&answer) {
+            println!("skipping blocked word: {}", answer);
+            continue;
+        }
+        clues.push((answer, clue.trim().to_string()));
+    }
+
+    clues
+}
+
+fn read_and_clean_words(blocklist: &Blocklist) -> Vec<String> {
     let stdin = io::stdin();
     let mut words: Vec<String> = Vec::new();
 
@@ -450,6 +1004,10 @@ fn read_and_clean_words() -> Vec<String> {
         }
         let mut cleaned_line = line.replace(" ", ""); // Remove spaces from the line
         cleaned_line.make_ascii_uppercase(); // Convert to lowercase
+        if !blocklist.allows(&cleaned_line) {
+            println!("skipping blocked word: {}", cleaned_line);
+            continue;
+        }
         words.push(cleaned_line);
     }
 
@@ -504,6 +1062,41 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn test_random_words() {
+        let blocklist = Blocklist::load("kill");
+        let words = random_words("cat\nkill\nox\nfresh-water\ndog\n", 10, &blocklist);
+        assert_eq!(words.len(), 2);
+        assert!(words.contains(&"CAT".to_string()));
+        assert!(words.contains(&"DOG".to_string()));
+    }
+
+    #[test]
+    fn test_word_search_missing() {
+        let words: Vec<String> = ["ABCDEFGHIJ", "CAT", "DOG"].iter().map(|w| w.to_string()).collect();
+        let (_, placements, missing) = word_search(&words, 5, &Blocklist::load(""));
+        assert_eq!(missing, vec!["ABCDEFGHIJ"]);
+        let mut placed: Vec<&str> = placements.iter().map(|p| p.word.as_str()).collect();
+        placed.sort();
+        assert_eq!(placed, vec!["CAT", "DOG"]);
+    }
+
+    #[test]
+    fn test_placement_cells() {
+        let word = "cat".to_string();
+        for dir in ALL_DIRS {
+            let word_grid = to_grid(&word, dir.clone());
+            let placement = Placement::from_offset(&word, &dir, 0, 0);
+            let cells = placement.cells();
+            for ((r, c), letter) in word_grid.indexed_iter() {
+                if !letter.is_empty() {
+                    let i = word.find(letter.letter).unwrap();
+                    assert_eq!(cells[i], (r, c), "{:?}", dir);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_to_grid() {
         let s = "hello".to_string();
@@ -0,0 +1,100 @@
+use crate::arithmetic::{digits_of, put_right, ColumnLayout, Problem, Rule};
+
+/// One partial product per digit of `b`, ones digit first, each already shifted into place.
+pub fn partial_products(a: i64, b: i64) -> Vec<i64> {
+    digits_of(b).iter().enumerate().map(|(i, d)| a * *d as i64 * 10_i64.pow(i as u32)).collect()
+}
+
+/// Carries made while multiplying `a` by one digit, indexed by the column of `a` they're written above.
+pub fn carries(a: i64, digit: u8) -> Vec<u8> {
+    let digits = digits_of(a);
+    let mut carries = vec![0; digits.len()];
+    let mut carry = 0;
+    for (i, d) in digits.iter().enumerate() {
+        carry = (d * digit + carry) / 10;
+        if i + 1 < digits.len() {
+            carries[i + 1] = carry;
+        }
+    }
+    carries
+}
+
+/** Lays out long multiplication with room to show the work.
+
+From the top: one small carry row per digit of the bottom number (the first partial
+product's carries sit right above the top number), the two operands, and then one row
+per partial product before the final answer line. A one-digit multiplier skips the
+partial products and goes straight to the answer. The key fills in every carry, every
+partial product with its placeholder zeros, and the answer.
+*/
+pub fn layout(problem: &Problem, key: bool) -> ColumnLayout {
+    let (a, b) = (problem.a.abs(), problem.b.abs());
+    let (da, db) = (digits_of(a).len(), digits_of(b).len());
+    // Room for the longest possible answer, and for the operator beside the bottom number.
+    let width = (da + db).max(da.max(db) + 1);
+    let partials = if db > 1 { db } else { 0 };
+    let rows = db + 2 + partials + 1;
+    let mut layout = ColumnLayout::blank(rows, width);
+    layout.small_rows.extend(0..db);
+    put_right(&mut layout.cells, db, width, &problem.a.to_string());
+    put_right(&mut layout.cells, db + 1, width, &problem.b.to_string());
+    layout.cells[(db + 1, 0)] = problem.operator.symbol();
+    layout.rules.push(Rule { row: db + 2, from: 0, to: width });
+    let answer_row = rows - 1;
+    if partials > 0 {
+        layout.rules.push(Rule { row: answer_row, from: 0, to: width });
+    }
+    if key {
+        for (i, digit) in digits_of(b).into_iter().enumerate() {
+            let carry_row = db - 1 - i;
+            for (column, carry) in carries(a, digit).into_iter().enumerate() {
+                if carry > 0 {
+                    layout.cells[(carry_row, width - 1 - column)] = (b'0' + carry) as char;
+                }
+            }
+        }
+        if partials > 0 {
+            for (i, partial) in partial_products(a, b).into_iter().enumerate() {
+                let text = format!("{:0>width$}", partial, width = i + 1);
+                put_right(&mut layout.cells, db + 2 + i, width, &text);
+            }
+        }
+        put_right(&mut layout.cells, answer_row, width, &problem.answer().to_string());
+    }
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::Operator;
+
+    #[test]
+    fn test_partial_products() {
+        assert_eq!(partial_products(123, 45), vec![615, 4920]);
+        assert_eq!(partial_products(123, 405), vec![615, 0, 49200]);
+        assert_eq!(partial_products(123, 45).iter().sum::<i64>(), 123 * 45);
+    }
+
+    #[test]
+    fn test_carries() {
+        // 58 x 7: 8x7 = 56 carries 5 into the tens.
+        assert_eq!(carries(58, 7), vec![0, 5]);
+        assert_eq!(carries(999, 9), vec![0, 8, 8]);
+        assert_eq!(carries(111, 2), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_layout() {
+        let problem = Problem::new(Operator::Multiply, 58, 7);
+        assert_eq!(problem.layout(false).to_text(), "\n 58\n× 7\n---\n\n");
+        assert_eq!(problem.layout(true).to_text(), " 5\n 58\n× 7\n---\n406\n");
+
+        let problem = Problem::new(Operator::Multiply, 123, 45);
+        let key = problem.layout(true).to_text();
+        assert_eq!(key, "   1\n  11\n  123\n×  45\n-----\n  615\n 4920\n-----\n 5535\n");
+        let blank = problem.layout(false).to_text();
+        assert_eq!(blank.lines().count(), key.lines().count());
+        assert!(!blank.contains("615"));
+    }
+}
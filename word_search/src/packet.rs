@@ -0,0 +1,94 @@
+use crate::pdf::{Body, Page, Sheet};
+
+/// Title used when a spec doesn't name its packet.
+const DEFAULT_TITLE: &str = "Worksheet packet";
+
+/** Reads a packet spec: one worksheet per line, written the way it would be on the command line.
+
+A `title` line names the packet, blank lines are skipped and `#` starts a comment.
+Returns the title and each worksheet's arguments.
+*/
+pub fn read_spec(spec: &str) -> (String, Vec<Vec<String>>) {
+    let mut title = String::from(DEFAULT_TITLE);
+    let mut sheets = vec![];
+    for line in spec.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if let Some(name) = line.strip_prefix("title ") {
+            title = name.trim().to_string();
+        } else if !line.is_empty() {
+            sheets.push(line.split_whitespace().map(|s| s.to_string()).collect());
+        }
+    }
+    (title, sheets)
+}
+
+/** Puts a packet together: a cover, every worksheet in order, then all the answer keys.
+
+The cover lists each worksheet and answer key with the page it starts on, and each of
+those first pages gets a bookmark.
+*/
+pub fn assemble(title: &str, sheets: Vec<Sheet>) -> Vec<Page> {
+    let mut contents = vec![];
+    let mut worksheets = vec![];
+    let mut keys = vec![];
+    // The cover is page 1.
+    let mut next = 2;
+    for (i, sheet) in sheets.iter().enumerate() {
+        contents.push(format!("{:>2}. {:<40} page {}", i + 1, sheet.title, next));
+        next += sheet.pages.len();
+    }
+    contents.push(String::new());
+    for (i, sheet) in sheets.iter().enumerate() {
+        if !sheet.key.is_empty() {
+            contents.push(format!("{:>2}. {:<40} page {}", i + 1, format!("{} - answer key", sheet.title), next));
+            next += sheet.key.len();
+        }
+    }
+    for sheet in sheets {
+        let mut pages = sheet.pages;
+        if let Some(first) = pages.first_mut() {
+            first.bookmark = Some(sheet.title.clone());
+        }
+        worksheets.extend(pages);
+        let mut key = sheet.key;
+        if let Some(first) = key.first_mut() {
+            first.bookmark = Some(format!("{} - answer key", sheet.title));
+        }
+        keys.extend(key);
+    }
+    let cover = Page { name_line: true, bookmark: Some(String::from("Contents")), ..Page::new(title.to_string(), Body::Text(contents)) };
+    std::iter::once(cover).chain(worksheets).chain(keys).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(title: &str, pages: usize, keys: usize) -> Sheet {
+        let page = |t: &str| Page::new(t.to_string(), Body::Text(vec![]));
+        Sheet { title: title.to_string(), pages: (0..pages).map(|_| page(title)).collect(), key: (0..keys).map(|_| page("key")).collect() }
+    }
+
+    #[test]
+    fn test_read_spec() {
+        let (title, sheets) = read_spec("title Week 3\n\n# warm up\naddition 10 99 10 99 some\npairs 10  # hex\n");
+        assert_eq!(title, "Week 3");
+        assert_eq!(sheets, vec![vec!["addition", "10", "99", "10", "99", "some"], vec!["pairs", "10"]]);
+        assert_eq!(read_spec("wordsearch").0, DEFAULT_TITLE);
+    }
+
+    #[test]
+    fn test_assemble() {
+        let pages = assemble("Week 3", vec![sheet("Word search", 1, 1), sheet("Addition", 2, 2), sheet("Pairs", 1, 1)]);
+        let titles: Vec<&str> = pages.iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(titles, vec!["Week 3", "Word search", "Addition", "Addition", "Pairs", "key", "key", "key", "key"]);
+        let bookmarks: Vec<(usize, &str)> = pages.iter().enumerate().filter_map(|(i, p)| Some((i + 1, p.bookmark.as_deref()?))).collect();
+        assert_eq!(
+            bookmarks,
+            vec![(1, "Contents"), (2, "Word search"), (3, "Addition"), (5, "Pairs"), (6, "Word search - answer key"), (7, "Addition - answer key"), (9, "Pairs - answer key")]
+        );
+        let Body::Text(contents) = &pages[0].body else { panic!("cover isn't text") };
+        assert!(contents[1].starts_with(" 2. Addition") && contents[1].ends_with("page 3"));
+        assert!(contents[6].ends_with("page 9"));
+    }
+}
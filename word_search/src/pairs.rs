@@ -0,0 +1,517 @@
+use grid::Grid;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::arithmetic::OperandRange;
+use crate::hex::{honeycomb, step};
+use crate::pdf::{Body, Page, Sheet};
+use crate::Direction;
+
+/// Fresh layouts tried before giving up on a set of settings.
+const MAX_ATTEMPTS: usize = 200;
+/// Most partial groups `group_values` will go through, enough for -500 to 500 in a triple.
+const MAX_PREFIXES: u64 = 2_000_000;
+/// Highlight colors for the groups in the answer key, cycled.
+const PAIR_COLORS: [&str; 6] = ["rgb(255, 214, 102)", "rgb(156, 219, 255)", "rgb(182, 236, 160)", "rgb(255, 176, 176)", "rgb(214, 190, 255)", "rgb(255, 200, 140)"];
+/// One direction along each of the three axes of the honeycomb, so every line is found once.
+const AXES: [Direction; 3] = [Direction::EE, Direction::SS, Direction::NE];
+
+/// A hex on the printed honeycomb, as (row, column).
+pub type Cell = (usize, usize);
+
+/// What the hexes in a group must do to count as an answer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// Two neighbors adding up to the target.
+    Sum,
+    /// Two neighbors multiplying to the target.
+    Product,
+    /// Two neighbors, one larger than the other by the target.
+    Difference,
+    /// Three hexes in a straight line adding up to the target.
+    Triple,
+}
+
+impl Rule {
+    /// Hexes in a group.
+    pub fn size(&self) -> usize {
+        match self {
+            Rule::Triple => 3,
+            _ => 2,
+        }
+    }
+
+    pub fn hits(&self, values: &[i64], target: i64) -> bool {
+        match self {
+            Rule::Sum | Rule::Triple => values.iter().sum::<i64>() == target,
+            Rule::Product => values.iter().product::<i64>() == target,
+            Rule::Difference => (values[0] - values[1]).abs() == target,
+        }
+    }
+
+    /// The instruction printed above the honeycomb, before the target.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Rule::Sum => "Find pairs that add up to:",
+            Rule::Product => "Find neighbors whose product is:",
+            Rule::Difference => "Find neighbors whose difference is:",
+            Rule::Triple => "Find three in a row that add up to:",
+        }
+    }
+
+    /// Between a group's numbers in the answer key. Only characters the PDF's Courier can show: × is, U+2212 isn't.
+    fn symbol(&self) -> &'static str {
+        match self {
+            Rule::Sum | Rule::Triple => " + ",
+            Rule::Product => " × ",
+            Rule::Difference => " - ",
+        }
+    }
+}
+
+/** A number honeycomb in the style of `worksheets/hex.html`: find groups of hexes that obey `rule`.
+
+Groups are neighboring pairs, or for `Rule::Triple` three hexes in a straight line.
+`planted` are the groups generation put there on purpose. Other groups may happen to hit
+the target too; `groups` finds all of them for the answer key.
+*/
+pub struct PairPuzzle {
+    pub rule: Rule,
+    pub target: i64,
+    pub numbers: Grid<i64>,
+    pub planted: Vec<Vec<Cell>>,
+}
+
+/// Every straight line of `len` hexes, each line once.
+fn lines(width: usize, height: usize, len: usize) -> Vec<Vec<Cell>> {
+    let mut lines = vec![];
+    for row in 0..height {
+        for col in 0..width {
+            for dir in &AXES {
+                let mut line = vec![(row, col)];
+                while line.len() < len {
+                    match step(width, height, line[line.len() - 1], dir) {
+                        Some(next) => line.push(next),
+                        None => break,
+                    }
+                }
+                if line.len() == len {
+                    lines.push(line);
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// Values for a whole group that obey the rule, e.g. (3, 8) and (8, 3) for a product of 24.
+///
+/// Only the group's last value is worked out from the others, so the work grows with the
+/// range to the power of one less than the group size, which is capped at `MAX_PREFIXES`.
+fn group_values(rule: Rule, target: i64, values: OperandRange) -> Result<Vec<Vec<i64>>, String> {
+    let span = (values.max - values.min + 1).max(0) as u64;
+    if span.checked_pow(rule.size() as u32 - 1).is_none_or(|n| n > MAX_PREFIXES) {
+        return Err(format!("numbers from {} to {} are too wide a range for {} {}", values.min, values.max, rule.title(), target));
+    }
+    let mut prefixes: Vec<Vec<i64>> = vec![vec![]];
+    for _ in 1..rule.size() {
+        prefixes = prefixes.into_iter().flat_map(|g| (values.min..=values.max).map(move |v| [g.clone(), vec![v]].concat())).collect();
+    }
+    let mut groups: Vec<Vec<i64>> = prefixes
+        .into_iter()
+        .flat_map(|prefix| {
+            let mut last = match rule {
+                Rule::Sum | Rule::Triple => vec![target - prefix.iter().sum::<i64>()],
+                Rule::Product => match prefix.iter().product::<i64>() {
+                    0 if target == 0 => (values.min..=values.max).collect(),
+                    0 => vec![],
+                    p => (target % p == 0).then_some(target / p).into_iter().collect(),
+                },
+                Rule::Difference => vec![prefix[0] - target, prefix[0] + target],
+            };
+            last.dedup();
+            last.into_iter().filter(|v| (values.min..=values.max).contains(v)).map(move |v| [prefix.clone(), vec![v]].concat())
+        })
+        .filter(|g| rule.hits(g, target))
+        .collect();
+    groups.sort();
+    Ok(groups)
+}
+
+/// Lines through any of `cells` that are fully numbered, obey the rule and weren't planted.
+fn accidental_hits(numbers: &Grid<Option<i64>>, through: &Grid<Vec<usize>>, lines: &[Vec<Cell>], planted: &[usize], cells: &[Cell], rule: Rule, target: i64) -> usize {
+    let mut hit: Vec<usize> = cells.iter().flat_map(|c| through[*c].iter().copied()).filter(|i| !planted.contains(i)).collect();
+    hit.sort();
+    hit.dedup();
+    hit.into_iter()
+        .filter(|i| {
+            let values: Option<Vec<i64>> = lines[*i].iter().map(|c| numbers[*c]).collect();
+            values.is_some_and(|v| rule.hits(&v, target))
+        })
+        .count()
+}
+
+impl PairPuzzle {
+    /** Numbers a `width` x `height` honeycomb with values from `values`.
+
+    `planted` groups that obey `rule` go on hexes that don't overlap. Every other hex is
+    numbered so that at most `accidental` further groups obey it as well, so with
+    `accidental` at 0 the puzzle has exactly `planted` answers. Fails when the values
+    can't make the target or the layout keeps running into the accidental limit.
+    */
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate<R: Rng>(rule: Rule, target: i64, width: usize, height: usize, values: OperandRange, planted: usize, accidental: usize, rng: &mut R) -> Result<PairPuzzle, String> {
+        let options = group_values(rule, target, values)?;
+        if planted > 0 && options.is_empty() {
+            return Err(format!("no numbers from {} to {} make {} {}", values.min, values.max, rule.title(), target));
+        }
+        for _ in 0..MAX_ATTEMPTS {
+            if let Some(puzzle) = Self::attempt(rule, target, (width, height), values, &options, planted, accidental, rng) {
+                return Ok(puzzle);
+            }
+        }
+        Err(format!("couldn't fit {} groups with at most {} accidental ones", planted, accidental))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn attempt<R: Rng>(rule: Rule, target: i64, (width, height): (usize, usize), values: OperandRange, options: &[Vec<i64>], planted: usize, accidental: usize, rng: &mut R) -> Option<PairPuzzle> {
+        let lines = lines(width, height, rule.size());
+        let mut through: Grid<Vec<usize>> = Grid::init(height, width, vec![]);
+        for (i, line) in lines.iter().enumerate() {
+            for cell in line {
+                through[*cell].push(i);
+            }
+        }
+        let mut order: Vec<usize> = (0..lines.len()).collect();
+        order.shuffle(rng);
+        let mut used = Grid::init(height, width, false);
+        let mut chosen = vec![];
+        for i in order {
+            if chosen.len() == planted {
+                break;
+            }
+            if lines[i].iter().all(|c| !used[*c]) {
+                lines[i].iter().for_each(|c| used[*c] = true);
+                chosen.push(i);
+            }
+        }
+        if chosen.len() < planted {
+            return None;
+        }
+        let mut numbers: Grid<Option<i64>> = Grid::init(height, width, None);
+        let mut extra = 0;
+        // Tries every option for `cells`, keeping a random one that stays under the accidental limit.
+        let mut assign = |numbers: &mut Grid<Option<i64>>, cells: &[Cell], options: &[Vec<i64>], rng: &mut R| -> Option<()> {
+            let mut fits = vec![];
+            for option in options {
+                cells.iter().zip(option).for_each(|(c, v)| numbers[*c] = Some(*v));
+                let hits = accidental_hits(numbers, &through, &lines, &chosen, cells, rule, target);
+                if extra + hits <= accidental {
+                    fits.push((option, hits));
+                }
+            }
+            let (option, hits) = fits.choose(rng)?;
+            cells.iter().zip(*option).for_each(|(c, v)| numbers[*c] = Some(*v));
+            extra += hits;
+            Some(())
+        };
+        for i in &chosen {
+            assign(&mut numbers, &lines[*i], options, rng)?;
+        }
+        let singles: Vec<Vec<i64>> = (values.min..=values.max).map(|v| vec![v]).collect();
+        let mut rest: Vec<Cell> = (0..height).flat_map(|r| (0..width).map(move |c| (r, c))).filter(|c| !used[*c]).collect();
+        rest.shuffle(rng);
+        for cell in rest {
+            assign(&mut numbers, &[cell], &singles, rng)?;
+        }
+        let numbers = Grid::from_vec(numbers.into_vec().into_iter().map(|v| v.unwrap_or(0)).collect(), width);
+        let planted = chosen.iter().map(|i| lines[*i].clone()).collect();
+        Some(PairPuzzle { rule, target, numbers, planted })
+    }
+
+    /// Every group that obeys the rule, planted or not.
+    pub fn groups(&self) -> Vec<Vec<Cell>> {
+        lines(self.numbers.cols(), self.numbers.rows(), self.rule.size())
+            .into_iter()
+            .filter(|line| self.rule.hits(&line.iter().map(|c| self.numbers[*c]).collect::<Vec<_>>(), self.target))
+            .collect()
+    }
+
+    /// Groups that obey the rule without being planted.
+    pub fn accidental(&self) -> Vec<Vec<Cell>> {
+        self.groups().into_iter().filter(|g| !self.planted.contains(g)).collect()
+    }
+
+    /// Plain-text honeycomb for the terminal, odd rows indented by half a cell.
+    pub fn render_text(&self) -> String {
+        let width = self.numbers.iter().map(|n| n.to_string().len()).max().unwrap_or(1) + 1;
+        let mut out = String::new();
+        for row in 0..self.numbers.rows() {
+            if row % 2 == 1 {
+                out.push_str(&" ".repeat(width.div_ceil(2)));
+            }
+            for n in self.numbers.iter_row(row) {
+                out.push_str(&format!("{:>w$}", n, w = width));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Each group as the rows and columns of its hexes, counting from 1.
+    pub fn answer_key(&self) -> Vec<String> {
+        let name = |(r, c): Cell| format!("row {}, column {} ({})", r + 1, c + 1, self.numbers[(r, c)]);
+        self.groups()
+            .into_iter()
+            .map(|mut group| {
+                if self.rule == Rule::Difference {
+                    group.sort_by_key(|c| std::cmp::Reverse(self.numbers[*c]));
+                }
+                group.into_iter().map(name).collect::<Vec<_>>().join(self.rule.symbol())
+            })
+            .collect()
+    }
+
+    fn render_honeycomb(&self, highlight: bool) -> String {
+        let groups = self.groups();
+        honeycomb(self.numbers.cols(), self.numbers.rows(), |row, col| {
+            let group = groups.iter().position(|g| g.contains(&(row, col)));
+            let class = match group {
+                Some(i) if highlight => format!("pair{}", i % PAIR_COLORS.len()),
+                _ => String::new(),
+            };
+            (self.numbers[(row, col)].to_string(), class)
+        })
+    }
+
+    /// The honeycomb for a PDF, with a line through each group on the answer key.
+    pub fn sheet(&self) -> Sheet {
+        let labels = Grid::from_vec(self.numbers.iter().map(|n| n.to_string()).collect(), self.numbers.cols());
+        let groups = self.groups();
+        let title = format!("{} {} ({} to find)", self.rule.title(), self.target, groups.len());
+        let lines = groups.into_iter().map(|g| (g[0], g[g.len() - 1])).collect();
+        Sheet {
+            pages: vec![Page { name_line: true, ..Page::new(title.clone(), Body::Honeycomb { labels: labels.clone(), lines: vec![] }) }],
+            key: vec![Page { notes: self.answer_key(), ..Page::new(format!("{} - answer key", title), Body::Honeycomb { labels, lines }) }],
+            title,
+        }
+    }
+
+    /// A printable page in the style of `worksheets/hex.html`, followed by an answer key page.
+    pub fn render_html(&self, size: usize) -> String {
+        let colors: String = PAIR_COLORS.iter().enumerate().map(|(i, c)| format!("        .hex-row div.pair{} {{ background: {}; }}\n", i, c)).collect();
+        let key: String = self.answer_key().iter().map(|k| format!("<li>{}</li>", k)).collect();
+        HTML_TEMPLATE
+            .replace("{size}", &size.to_string())
+            .replace("{font}", &(size / 3).to_string())
+            .replace("{colors}", &colors)
+            .replace("{title}", self.rule.title())
+            .replace("{target}", &self.target.to_string())
+            .replace("{count}", &self.groups().len().to_string())
+            .replace("{puzzle}", &self.render_honeycomb(false))
+            .replace("{answers}", &self.render_honeycomb(true))
+            .replace("{key}", &key)
+    }
+}
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Hexagonal Grid</title>
+    <style>
+        @import url('https://fonts.googleapis.com/css2?family=Martian+Mono:wdth,wght@75..112.5,100..800&display=swap');
+
+        .martian-mono {
+            font-family: "Martian Mono", monospace;
+            font-optical-sizing: auto;
+            font-weight: 500;
+            font-style: normal;
+            font-variation-settings:
+                "width" 112.5;
+        }
+
+        #title {
+            font-size: 30px;
+            font-weight: bold;
+            width: 100%;
+            padding-bottom: 20px;
+        }
+
+        .sum {
+            color: red;
+        }
+
+        body {
+            background-color: dimgray;
+        }
+
+        .main {
+            --s: {size}px;
+            /* size  */
+            --m: 4px;
+            /* margin */
+            page-break-inside: avoid;
+            box-sizing: border-box;
+            width: 8.5in;
+            height: 11in;
+            padding: 15mm;
+            margin: 0 auto;
+            display: flex;
+            flex-flow: row wrap;
+            align-content: space-evenly;
+            justify-content: space-evenly;
+            page-break-after: always;
+            background: white;
+            overflow-y: hidden;
+        }
+
+        .hex-row {
+            font-size: 0;
+            /*disable white space between inline block element */
+            white-space: nowrap;
+        }
+
+        .hex-row.odd {
+            margin-left: calc(var(--s)/2 + var(--m));
+        }
+
+        .hex-row div {
+            width: var(--s);
+            margin: var(--m);
+            height: calc(var(--s)*1.1547);
+            display: inline-grid;
+            font-size: {font}px;
+            align-content: center;
+            justify-content: center;
+            clip-path: polygon(0% 25%, 0% 75%, 50% 100%, 100% 75%, 100% 25%, 50% 0%);
+            background: rgb(236, 236, 236);
+            margin-bottom: calc(var(--m) - var(--s)*0.2885);
+        }
+
+{colors}
+        .key {
+            width: 100%;
+            columns: 2;
+            font-size: 14px;
+        }
+
+        @media print {
+            @page {
+                width: 8.5in;
+                height: 11in;
+                margin: 0mm;
+            }
+            body {
+                height: fit-content;
+                width: fit-content;
+                padding: 0;
+                margin: 0;
+                background-color: #fff;
+            }
+            .main {
+                margin: 0;
+            }
+        }
+    </style>
+</head>
+
+<body>
+    <div class="martian-mono main">
+        <div id="title">{title} <span class="sum">{target}</span> ({count} to find)</div>
+{puzzle}    </div>
+    <div class="martian-mono main">
+        <div id="title">Answer key: {title} <span class="sum">{target}</span> ({count} to find)</div>
+{answers}        <ol class="key">{key}</ol>
+    </div>
+</body>
+
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let mut rng = rand::thread_rng();
+        for accidental in [0, 3] {
+            let puzzle = PairPuzzle::generate(Rule::Sum, 4, 8, 9, OperandRange::new(-4, 8), 10, accidental, &mut rng).unwrap();
+            assert_eq!(puzzle.planted.len(), 10);
+            assert!(puzzle.numbers.iter().all(|n| (-4..=8).contains(n)));
+            for group in &puzzle.planted {
+                assert!(lines(8, 9, 2).contains(group));
+                assert_eq!(puzzle.numbers[group[0]] + puzzle.numbers[group[1]], 4);
+            }
+            assert!(puzzle.accidental().len() <= accidental);
+            assert_eq!(puzzle.groups().len(), 10 + puzzle.accidental().len());
+        }
+    }
+
+    #[test]
+    fn test_variants() {
+        let mut rng = rand::thread_rng();
+        let digits = OperandRange::new(1, 9);
+        for (rule, target) in [(Rule::Product, 24), (Rule::Difference, 3), (Rule::Triple, 15)] {
+            let puzzle = PairPuzzle::generate(rule, target, 8, 9, digits, 6, 0, &mut rng).unwrap();
+            // No accidental groups allowed, so the planted ones are the whole answer key.
+            assert_eq!(puzzle.groups().len(), 6, "{:?}", rule);
+            assert_eq!(puzzle.answer_key().len(), 6);
+            assert!(puzzle.planted.iter().all(|g| g.len() == rule.size()));
+        }
+        assert!(Rule::Difference.hits(&[2, 5], 3) && Rule::Difference.hits(&[5, 2], 3));
+        assert!(PairPuzzle::generate(Rule::Product, 23, 6, 6, digits, 1, 0, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_group_values() {
+        let digits = OperandRange::new(-3, 9);
+        for (rule, target) in [(Rule::Sum, 4), (Rule::Product, 0), (Rule::Product, -12), (Rule::Difference, 0), (Rule::Difference, 3), (Rule::Triple, 10)] {
+            let every: Vec<Vec<i64>> = (0..rule.size()).fold(vec![vec![]], |groups, _| groups.into_iter().flat_map(|g: Vec<i64>| (-3..=9).map(move |v| [g.clone(), vec![v]].concat())).collect());
+            let hits: Vec<Vec<i64>> = every.into_iter().filter(|g| rule.hits(g, target)).collect();
+            assert_eq!(group_values(rule, target, digits).unwrap(), hits, "{:?} {}", rule, target);
+        }
+        // Wide ranges only go through the first values of each group.
+        let fits = (-500..=500i64).map(|a| (-500..=500i64).filter(|b| (-500..=500).contains(&(15 - a - b))).count()).sum::<usize>();
+        assert_eq!(group_values(Rule::Triple, 15, OperandRange::new(-500, 500)).unwrap().len(), fits);
+        assert!(group_values(Rule::Triple, 15, OperandRange::new(-5000, 5000)).is_err());
+    }
+
+    #[test]
+    fn test_lines() {
+        // On a 3x3 honeycomb: the 3 rows plus two lines along each diagonal.
+        let triples = lines(3, 3, 3);
+        assert_eq!(triples.len(), 7);
+        assert!(triples.contains(&vec![(0, 0), (1, 0), (2, 1)]));
+        assert!(triples.contains(&vec![(2, 0), (1, 0), (0, 1)]));
+        assert!(!triples.contains(&vec![(0, 2), (1, 2), (2, 3)]));
+        assert_eq!(lines(3, 3, 2).len(), 16);
+    }
+
+    #[test]
+    fn test_impossible() {
+        let mut rng = rand::thread_rng();
+        assert!(PairPuzzle::generate(Rule::Sum, 30, 6, 6, OperandRange::new(1, 9), 3, 0, &mut rng).is_err());
+        // A 2x2 honeycomb only has room for two pairs.
+        assert!(PairPuzzle::generate(Rule::Sum, 10, 2, 2, OperandRange::new(1, 9), 3, 0, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let puzzle = PairPuzzle { rule: Rule::Sum, target: 5, numbers: Grid::from_vec(vec![1, 4, 7, 9, 2, 3], 3), planted: vec![vec![(1, 1), (1, 2)]] };
+        assert_eq!(puzzle.groups(), vec![vec![(0, 0), (0, 1)], vec![(1, 1), (1, 2)]]);
+        assert_eq!(puzzle.accidental(), vec![vec![(0, 0), (0, 1)]]);
+        assert_eq!(puzzle.render_text(), " 1 4 7\n  9 2 3\n");
+        assert_eq!(puzzle.answer_key()[1], "row 2, column 2 (2) + row 2, column 3 (3)");
+        let html = puzzle.render_html(50);
+        assert_eq!(html.matches("class=\"pair0\"").count(), 2);
+        assert!(html.contains("Find pairs that add up to: <span class=\"sum\">5</span> (2 to find)"));
+        assert_eq!(puzzle.sheet().title, "Find pairs that add up to: 5 (2 to find)");
+
+        let difference = PairPuzzle { rule: Rule::Difference, target: 3, ..puzzle };
+        assert_eq!(difference.answer_key(), vec!["row 1, column 2 (4) - row 1, column 1 (1)", "row 1, column 3 (7) - row 1, column 2 (4)"]);
+    }
+}
@@ -0,0 +1,273 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use grid::Grid;
+use printpdf::{BuiltinFont, IndirectFontRef, Line, Mm, PdfDocument, PdfLayerReference, Point};
+
+use crate::arithmetic::ColumnLayout;
+use crate::layout::{PageSetup, COURIER_WIDTH, PT_TO_MM};
+
+const TITLE_SIZE: f32 = 18.0;
+/// Size of the name and date labels, the page number and notes.
+const LABEL_SIZE: f32 = 11.0;
+/// Size of plain text pages, such as a packet's cover.
+const TEXT_SIZE: f32 = 14.0;
+/// Carry and borrow digits are printed at this fraction of the normal size.
+const SMALL_SCALE: f32 = 0.7;
+/// Largest letter cell or hex width in mm, so small puzzles don't print comically big.
+const MAX_CELL: f32 = 14.0;
+/// Thickness in points of the lines marking answers in a key.
+const ANSWER_LINE: f32 = 2.5;
+
+/// A cell of a letter grid or honeycomb, as (row, column).
+pub type Cell = (usize, usize);
+
+/// What fills the space between a page's header and footer.
+pub enum Body {
+    /// Problems spread over an even grid.
+    Problems(Vec<ColumnLayout>),
+    /// One grid of letters, like a word search; each line is struck from one cell's center to another's.
+    Letters { grid: Grid<char>, lines: Vec<(Cell, Cell)> },
+    /// A honeycomb with odd rows pushed half a hex right, like `worksheets/hex.html`, with lines as above.
+    Honeycomb { labels: Grid<String>, lines: Vec<(Cell, Cell)> },
+    /// Plain lines of text, top to bottom.
+    Text(Vec<String>),
+}
+
+/// One printed page: a title and what goes under it.
+pub struct Page {
+    pub title: String,
+    pub body: Body,
+    /// Short entries printed in columns along the bottom of the body, like a word bank.
+    pub notes: Vec<String>,
+    /// Whether to print lines for the student's name and the date; answer keys leave them off.
+    pub name_line: bool,
+    /// Outline entry pointing at this page, if any.
+    pub bookmark: Option<String>,
+}
+
+impl Page {
+    pub fn new(title: String, body: Body) -> Page {
+        Page { title, body, notes: vec![], name_line: false, bookmark: None }
+    }
+}
+
+/// A worksheet's pages and the answer key pages that go with them.
+pub struct Sheet {
+    pub title: String,
+    pub pages: Vec<Page>,
+    pub key: Vec<Page>,
+}
+
+impl Sheet {
+    /// Writes the worksheet followed by its answer key.
+    pub fn save(self, setup: &PageSetup, path: &Path) -> Result<(), Box<dyn Error>> {
+        let pages: Vec<Page> = self.pages.into_iter().chain(self.key).collect();
+        save_pdf(&self.title, &pages, setup, path)
+    }
+}
+
+fn draw_line(layer: &PdfLayerReference, x1: f32, y1: f32, x2: f32, y2: f32) {
+    layer.add_line(Line {
+        points: vec![(Point::new(Mm(x1), Mm(y1)), false), (Point::new(Mm(x2), Mm(y2)), false)],
+        is_closed: false,
+    });
+}
+
+/// Draws answer lines between cell centers, thicker than the rules around them.
+fn draw_answer_lines(layer: &PdfLayerReference, lines: &[(Cell, Cell)], center: impl Fn(Cell) -> (f32, f32)) {
+    layer.set_outline_thickness(ANSWER_LINE);
+    for (from, to) in lines {
+        let ((x1, y1), (x2, y2)) = (center(*from), center(*to));
+        draw_line(layer, x1, y1, x2, y2);
+    }
+    layer.set_outline_thickness(1.0);
+}
+
+/// Writes `text` centered on (`x`, `y`).
+fn draw_centered(layer: &PdfLayerReference, font: &IndirectFontRef, text: &str, size: f32, x: f32, y: f32) {
+    let x = x - PageSetup::text_width(text, size) / 2.0;
+    layer.use_text(text, size, Mm(x), Mm(y - size * PT_TO_MM * 0.35), font);
+}
+
+/// Draws a layout with its top-left corner at (`x`, `top`), in mm from the bottom-left of the page.
+fn draw_layout(layer: &PdfLayerReference, font: &IndirectFontRef, layout: &ColumnLayout, setup: &PageSetup, x: f32, top: f32) {
+    let (cw, ch) = (setup.cell_width(), setup.cell_height());
+    for ((row, col), c) in layout.cells.indexed_iter() {
+        if *c == ' ' {
+            continue;
+        }
+        let size = if layout.small_rows.contains(&row) { setup.font_size * SMALL_SCALE } else { setup.font_size };
+        let glyph_w = size * PT_TO_MM * COURIER_WIDTH;
+        let cx = x + col as f32 * cw + (cw - glyph_w) / 2.0;
+        let cy = top - (row + 1) as f32 * ch + ch * 0.25;
+        layer.use_text(c.to_string(), size, Mm(cx), Mm(cy), font);
+    }
+    for rule in &layout.rules {
+        let y = top - rule.row as f32 * ch;
+        draw_line(layer, x + rule.from as f32 * cw, y, x + rule.to as f32 * cw, y);
+    }
+}
+
+/// Draws the title, the name and date lines if wanted, and "Page n of m" at the bottom.
+fn draw_header(layer: &PdfLayerReference, font: &IndirectFontRef, page: &Page, setup: &PageSetup, number: usize, total: usize) {
+    layer.use_text(page.title.as_str(), TITLE_SIZE, Mm(setup.margin), Mm(setup.title_y(TITLE_SIZE)), font);
+    if page.name_line {
+        let y = setup.name_y();
+        let label_w = PageSetup::text_width("Name ", LABEL_SIZE);
+        let middle = setup.width / 2.0;
+        layer.use_text("Name", LABEL_SIZE, Mm(setup.margin), Mm(y), font);
+        draw_line(layer, setup.margin + label_w, y, middle - 10.0, y);
+        layer.use_text("Date", LABEL_SIZE, Mm(middle), Mm(y), font);
+        draw_line(layer, middle + label_w, y, setup.width - setup.margin, y);
+    }
+    let footer = format!("Page {} of {}", number, total);
+    let x = (setup.width - PageSetup::text_width(&footer, LABEL_SIZE)) / 2.0;
+    layer.use_text(footer, LABEL_SIZE, Mm(x), Mm(setup.footer_y()), font);
+}
+
+/// Height the notes take up when set in as many columns as fit across the page.
+fn notes_height(notes: &[String], setup: &PageSetup) -> (f32, usize, f32) {
+    let line_h = LABEL_SIZE * PT_TO_MM * 1.5;
+    let column_w = notes.iter().map(|n| PageSetup::text_width(n, LABEL_SIZE)).fold(0.0, f32::max) + 6.0;
+    let columns = (((setup.width - 2.0 * setup.margin) / column_w).floor() as usize).max(1);
+    let rows = notes.len().div_ceil(columns);
+    let height = if rows == 0 { 0.0 } else { (rows as f32 + 0.5) * line_h };
+    (height, rows, column_w)
+}
+
+/// Draws notes in columns, filling each column top to bottom from `top`.
+fn draw_notes(layer: &PdfLayerReference, font: &IndirectFontRef, notes: &[String], setup: &PageSetup, top: f32) {
+    let (_, rows, column_w) = notes_height(notes, setup);
+    let line_h = LABEL_SIZE * PT_TO_MM * 1.5;
+    for (i, note) in notes.iter().enumerate() {
+        let (x, y) = (setup.margin + (i / rows) as f32 * column_w, top - (i % rows + 1) as f32 * line_h);
+        layer.use_text(note.as_str(), LABEL_SIZE, Mm(x), Mm(y), font);
+    }
+}
+
+/// Draws a page's body between its header and footer, with any notes along the bottom.
+fn draw_body(layer: &PdfLayerReference, font: &IndirectFontRef, page: &Page, setup: &PageSetup) {
+    let (top, bottom) = setup.body();
+    let (notes_h, _, _) = notes_height(&page.notes, setup);
+    draw_notes(layer, font, &page.notes, setup, bottom + notes_h);
+    let bottom = bottom + notes_h;
+    let body_w = setup.width - 2.0 * setup.margin;
+    match &page.body {
+        Body::Problems(layouts) => {
+            let rows = layouts.iter().map(|l| l.cells.rows()).max().unwrap_or(0);
+            let cols = layouts.iter().map(|l| l.cells.cols()).max().unwrap_or(0);
+            if rows == 0 || cols == 0 {
+                return;
+            }
+            for (layout, (x, top)) in layouts.iter().zip(setup.grid(layouts.len(), rows, cols)) {
+                // Right-align narrower problems within their box so the ones columns line up.
+                let shift = (cols - layout.cells.cols()) as f32 * setup.cell_width();
+                draw_layout(layer, font, layout, setup, x + shift, top);
+            }
+        }
+        Body::Letters { grid, lines } => {
+            let cell = (body_w / grid.cols() as f32).min((top - bottom) / grid.rows() as f32).min(MAX_CELL);
+            let left = (setup.width - cell * grid.cols() as f32) / 2.0;
+            let top = top - ((top - bottom) - cell * grid.rows() as f32) / 2.0;
+            let center = |(r, c): Cell| (left + (c as f32 + 0.5) * cell, top - (r as f32 + 0.5) * cell);
+            let size = cell * 0.6 / PT_TO_MM;
+            for ((r, c), letter) in grid.indexed_iter() {
+                let (x, y) = center((r, c));
+                draw_centered(layer, font, &letter.to_string(), size, x, y);
+            }
+            draw_answer_lines(layer, lines, center);
+        }
+        Body::Honeycomb { labels, lines } => {
+            let (rows, cols) = (labels.rows() as f32, labels.cols() as f32);
+            // Pointy-top hexes: each row overlaps the one above by a quarter of a hex's height.
+            let hex_w = (body_w / (cols + 0.5)).min((top - bottom) / (1.1547 * (1.0 + 0.75 * (rows - 1.0)))).min(MAX_CELL * 1.5);
+            let hex_h = hex_w * 1.1547;
+            let left = (setup.width - hex_w * (cols + 0.5)) / 2.0;
+            let top = top - ((top - bottom) - hex_h * (1.0 + 0.75 * (rows - 1.0))) / 2.0;
+            let center = |(r, c): Cell| (left + hex_w * (c as f32 + 0.5 + 0.5 * (r % 2) as f32), top - hex_h * (0.5 + 0.75 * r as f32));
+            let size = hex_w * 0.4 / PT_TO_MM;
+            for ((r, c), label) in labels.indexed_iter() {
+                let (x, y) = center((r, c));
+                let corners = (0..6).map(|k| {
+                    let angle = (30.0 + 60.0 * k as f32).to_radians();
+                    (Point::new(Mm(x + hex_h / 2.0 * angle.cos()), Mm(y + hex_h / 2.0 * angle.sin())), false)
+                });
+                layer.add_line(Line { points: corners.collect(), is_closed: true });
+                draw_centered(layer, font, label, size, x, y);
+            }
+            draw_answer_lines(layer, lines, center);
+        }
+        Body::Text(text) => {
+            let line_h = TEXT_SIZE * PT_TO_MM * 1.6;
+            for (i, line) in text.iter().enumerate() {
+                layer.use_text(line.as_str(), TEXT_SIZE, Mm(setup.margin), Mm(top - (i + 1) as f32 * line_h), font);
+            }
+        }
+    }
+}
+
+/// Writes the pages to a PDF file, one page each, numbered and with their bookmarks.
+pub fn save_pdf(title: &str, pages: &[Page], setup: &PageSetup, path: &Path) -> Result<(), Box<dyn Error>> {
+    let (doc, first_page, first_layer) = PdfDocument::new(title, Mm(setup.width), Mm(setup.height), "Layer 1");
+    let font = doc.add_builtin_font(BuiltinFont::Courier)?;
+    for (i, page) in pages.iter().enumerate() {
+        let (page_index, layer_index) = if i == 0 {
+            (first_page, first_layer)
+        } else {
+            doc.add_page(Mm(setup.width), Mm(setup.height), "Layer 1")
+        };
+        if let Some(bookmark) = &page.bookmark {
+            doc.add_bookmark(bookmark.as_str(), page_index);
+        }
+        let layer = doc.get_page(page_index).get_layer(layer_index);
+        draw_header(&layer, &font, page, setup, i + 1, pages.len());
+        draw_body(&layer, &font, page, setup);
+    }
+    doc.save(&mut BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::{Operator, Problem};
+
+    #[test]
+    fn test_save_pdf() {
+        let problems = [Problem::new(Operator::Add, 58, 167), Problem::new(Operator::Add, 3, 4)];
+        let sheet = Sheet {
+            title: "Addition".to_string(),
+            pages: vec![Page { name_line: true, ..Page::new("Addition".to_string(), Body::Problems(problems.iter().map(|p| p.layout(false)).collect())) }],
+            key: vec![Page::new("Answer key".to_string(), Body::Problems(problems.iter().map(|p| p.layout(true)).collect()))],
+        };
+        let path = std::env::temp_dir().join("word_search_test_save_pdf.pdf");
+        sheet.save(&PageSetup::letter(17.0), &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.starts_with(b"%PDF"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_puzzles() {
+        let letters = Grid::from_vec("CATXDOGX".chars().collect(), 4);
+        let labels = Grid::from_vec(["1", "4", "7", "9", "2", "3"].iter().map(|s| s.to_string()).collect(), 3);
+        let pages = vec![
+            Page {
+                notes: vec!["CAT".to_string(), "DOG".to_string()],
+                bookmark: Some("Word search".to_string()),
+                ..Page::new("Word search".to_string(), Body::Letters { grid: letters, lines: vec![((0, 0), (0, 2))] })
+            },
+            Page::new("Pairs".to_string(), Body::Honeycomb { labels, lines: vec![((1, 1), (1, 2))] }),
+            Page::new("Contents".to_string(), Body::Text(vec!["Word search".to_string()])),
+        ];
+        let path = std::env::temp_dir().join("word_search_test_save_puzzles.pdf");
+        save_pdf("Packet", &pages, &PageSetup::letter(17.0), &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.starts_with(b"%PDF"));
+        assert!(bytes.windows(b"/Outlines".len()).any(|w| w == b"/Outlines"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use std::io::{self, Write};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use grid::Grid;
+
+use crate::{Character, Placement};
+
+/// Colors for found words, cycled so neighbouring words are easy to tell apart.
+const FOUND_COLORS: [Color; 6] = [Color::Green, Color::Cyan, Color::Magenta, Color::Yellow, Color::Blue, Color::Red];
+
+/// State of an interactive word search: the player picks a start cell, then an end cell.
+pub struct Game {
+    grid: Grid<Character>,
+    placements: Vec<Placement>,
+    found: Vec<bool>,
+    cursor: (usize, usize),
+    start: Option<(usize, usize)>,
+    message: String,
+}
+
+impl Game {
+    pub fn new(grid: Grid<Character>, placements: Vec<Placement>) -> Game {
+        let found = vec![false; placements.len()];
+        Game { grid, placements, found, cursor: (0, 0), start: None, message: String::from("Arrow keys to move, SPACE to pick the first and last letter, Q to quit.") }
+    }
+
+    pub fn is_won(&self) -> bool {
+        self.found.iter().all(|f| *f)
+    }
+
+    /// Moves the cursor, staying inside the grid.
+    pub fn move_cursor(&mut self, dr: isize, dc: isize) {
+        let row = (self.cursor.0 as isize + dr).clamp(0, self.grid.rows() as isize - 1);
+        let col = (self.cursor.1 as isize + dc).clamp(0, self.grid.cols() as isize - 1);
+        self.cursor = (row as usize, col as usize);
+    }
+
+    /** Checks a selection against the recorded placements.
+
+    Either end may be picked first. Returns the index of the newly found word, if any.
+    */
+    pub fn check(&mut self, start: (usize, usize), end: (usize, usize)) -> Option<usize> {
+        let hit = self.placements.iter().enumerate().position(|(i, p)| {
+            let cells = p.cells();
+            let (first, last) = (cells[0], cells[cells.len() - 1]);
+            !self.found[i] && ((first, last) == (start, end) || (first, last) == (end, start))
+        })?;
+        self.found[hit] = true;
+        Some(hit)
+    }
+
+    /// Handles SPACE/ENTER: the first press marks a start cell, the second checks the word.
+    pub fn select(&mut self) {
+        match self.start.take() {
+            None => {
+                self.start = Some(self.cursor);
+                self.message = String::from("Now pick the last letter.");
+            }
+            Some(start) if start == self.cursor => {
+                self.message = String::from("Selection cleared.");
+            }
+            Some(start) => match self.check(start, self.cursor) {
+                Some(i) if self.is_won() => self.message = format!("{}! You found them all!", self.placements[i].word),
+                Some(i) => self.message = format!("You found {}!", self.placements[i].word),
+                None => self.message = String::from("Not a word. Try again!"),
+            },
+        }
+    }
+
+    /// Color of the word found through this cell, if any.
+    fn found_color(&self, cell: (usize, usize)) -> Option<Color> {
+        self.placements
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(i, p)| self.found[*i] && p.cells().contains(&cell))
+            .map(|(i, _)| FOUND_COLORS[i % FOUND_COLORS.len()])
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        queue!(out, Clear(ClearType::All))?;
+        for row in 0..self.grid.rows() {
+            queue!(out, MoveTo(0, row as u16))?;
+            for col in 0..self.grid.cols() {
+                let cell = (row, col);
+                if cell == self.cursor {
+                    queue!(out, SetAttribute(Attribute::Reverse))?;
+                } else if Some(cell) == self.start {
+                    queue!(out, SetBackgroundColor(Color::DarkYellow), SetForegroundColor(Color::Black))?;
+                } else if let Some(color) = self.found_color(cell) {
+                    queue!(out, SetBackgroundColor(color), SetForegroundColor(Color::Black))?;
+                }
+                queue!(out, Print(self.grid[cell].letter), SetAttribute(Attribute::Reset), ResetColor, Print(' '))?;
+            }
+        }
+        let bank_col = self.grid.cols() as u16 * 2 + 4;
+        for (i, placement) in self.placements.iter().enumerate() {
+            queue!(out, MoveTo(bank_col, i as u16))?;
+            if self.found[i] {
+                queue!(out, SetForegroundColor(FOUND_COLORS[i % FOUND_COLORS.len()]), SetAttribute(Attribute::CrossedOut))?;
+            }
+            queue!(out, Print(&placement.word), SetAttribute(Attribute::Reset), ResetColor)?;
+        }
+        let found = self.found.iter().filter(|f| **f).count();
+        queue!(out, MoveTo(0, self.grid.rows() as u16 + 1), Print(format!("{}/{} found. {}", found, self.placements.len(), self.message)))?;
+        out.flush()
+    }
+}
+
+/// Runs the game in the terminal until the player quits.
+pub fn run(grid: Grid<Character>, placements: Vec<Placement>) -> io::Result<()> {
+    let mut game = Game::new(grid, placements);
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, Hide)?;
+    let result = (|| -> io::Result<()> {
+        loop {
+            game.draw(&mut out)?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Up => game.move_cursor(-1, 0),
+                KeyCode::Down => game.move_cursor(1, 0),
+                KeyCode::Left => game.move_cursor(0, -1),
+                KeyCode::Right => game.move_cursor(0, 1),
+                KeyCode::Char(' ') | KeyCode::Enter => game.select(),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                _ => {}
+            }
+        }
+    })();
+    execute!(out, LeaveAlternateScreen, Show)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Direction;
+
+    fn game() -> Game {
+        let mut grid = Grid::init(4, 4, Character::from('X'));
+        let placements = vec![
+            Placement { word: "CAT".to_string(), row: 0, col: 0, dir: Direction::EE },
+            Placement { word: "DOG".to_string(), row: 3, col: 0, dir: Direction::NE },
+        ];
+        for p in &placements {
+            for (cell, c) in p.cells().into_iter().zip(p.word.chars()) {
+                grid[cell].letter = c;
+            }
+        }
+        Game::new(grid, placements)
+    }
+
+    #[test]
+    fn test_check() {
+        let mut game = game();
+        assert_eq!(game.check((0, 0), (0, 1)), None);
+        assert_eq!(game.check((0, 0), (0, 2)), Some(0));
+        // Already found.
+        assert_eq!(game.check((0, 0), (0, 2)), None);
+        assert!(!game.is_won());
+        // Picked backwards, from the G to the D.
+        assert_eq!(game.check((1, 2), (3, 0)), Some(1));
+        assert!(game.is_won());
+    }
+
+    #[test]
+    fn test_select() {
+        let mut game = game();
+        game.move_cursor(-5, 10);
+        assert_eq!(game.cursor, (0, 3));
+        game.move_cursor(0, -1);
+        game.select();
+        assert_eq!(game.start, Some((0, 2)));
+        game.move_cursor(0, -2);
+        game.select();
+        assert_eq!(game.start, None);
+        assert_eq!(game.message, "You found CAT!");
+        assert_eq!(game.found_color((0, 1)), Some(Color::Green));
+        assert_eq!(game.found_color((3, 0)), None);
+    }
+}